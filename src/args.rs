@@ -1,27 +1,36 @@
-use bpaf::Bpaf;
-
-#[derive(Debug, Clone)]
-pub enum AngleUnit {
-    Radian,
-    Degrees,
-}
+use bpaf::{Bpaf, Parser, construct, short};
+use evar::models::{AngleUnit, DEFAULT_MAX_DEPTH, DisplayMode};
 
 #[derive(Bpaf, Debug)]
 #[bpaf(options, version)]
 /// Modern ergonomic math calculator inspired by eva
 pub struct Args {
-    /// Use degrees instead of radians
-    #[bpaf(
-        short('d'),
-        long("degrees"),
-        flag(AngleUnit::Degrees, AngleUnit::Radian)
-    )]
-    pub angle_unit: AngleUnit,
+    #[bpaf(external(angle_unit))]
+    pub angle_unit: Option<AngleUnit>,
 
     /// Number of decimal places in output (0-63) [default: None]
     #[bpaf(short, long, guard(fix_in_range, "fix must be in range 0-63"))]
     pub fix: Option<usize>,
 
+    /// Print results as dec, hex, bin, oct, sci, eng or si; hex32, bin8 etc.
+    /// show negative integers in two's complement [default: dec]
+    #[bpaf(long, argument("MODE"))]
+    pub format: Option<DisplayMode>,
+
+    /// Promote roots, powers and logarithms of negative numbers to complex numbers
+    #[bpaf(short, long)]
+    pub complex: bool,
+
+    /// Maximum depth of nested function calls (at most 10000)
+    #[bpaf(
+        long,
+        argument("DEPTH"),
+        fallback(DEFAULT_MAX_DEPTH),
+        display_fallback,
+        guard(depth_in_range, "max-depth must be at most 10000")
+    )]
+    pub max_depth: usize,
+
     /// Disable colored output
     #[bpaf(long)]
     pub no_color: bool,
@@ -29,6 +38,37 @@ pub struct Args {
     /// Print parsed expression for debug purpose
     #[bpaf(long)]
     pub debug: bool,
+
+    /// Print one JSON object per evaluated statement
+    #[bpaf(long)]
+    pub json: bool,
+
+    /// Keep evaluating after an error in batch mode
+    #[bpaf(short, long)]
+    pub keep_going: bool,
+
+    /// Load user definitions from the named workspace and save them on exit
+    #[bpaf(
+        short,
+        long,
+        argument("NAME"),
+        guard(
+            workspace_name,
+            "workspace names may only contain letters, digits, '-' and '_'"
+        )
+    )]
+    pub workspace: Option<String>,
+
+    /// Expressions or script files to evaluate instead of starting the REPL
+    #[bpaf(positional("EXPR|FILE"), many)]
+    pub exprs: Vec<String>,
+}
+
+/// Largest call depth the interpreter thread's stack is sized for.
+pub const MAX_DEPTH: usize = 10_000;
+
+fn depth_in_range(depth: &usize) -> bool {
+    *depth <= MAX_DEPTH
 }
 
 fn fix_in_range(fix: &Option<usize>) -> bool {
@@ -37,3 +77,24 @@ fn fix_in_range(fix: &Option<usize>) -> bool {
         Some(fix) => (0..64).contains(fix),
     }
 }
+
+fn workspace_name(name: &Option<String>) -> bool {
+    name.as_ref().is_none_or(|name| {
+        !name.is_empty()
+            && name
+                .chars()
+                .all(|c| c.is_alphanumeric() || c == '-' || c == '_')
+    })
+}
+
+fn angle_unit() -> impl Parser<Option<AngleUnit>> {
+    let degrees = short('d')
+        .long("degrees")
+        .help("Use degrees instead of radians")
+        .req_flag(AngleUnit::Degrees);
+    let gradians = short('g')
+        .long("gradians")
+        .help("Use gradians instead of radians")
+        .req_flag(AngleUnit::Gradians);
+    construct!([degrees, gradians]).optional()
+}
@@ -0,0 +1,259 @@
+use chumsky::prelude::*;
+use evar::models::{AngleUnit, DisplayMode, ParseError};
+
+/// A REPL command. Commands start with `:`, except for the bare `help`,
+/// `exit` and `quit`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Command {
+    Help(Option<String>),
+    Quit,
+    Vars,
+    Funcs,
+    Del(String),
+    Reset,
+    Set(Option<Setting>),
+    Load(String),
+    Save(String),
+    History,
+    Time(String),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Setting {
+    Fix(Option<usize>),
+    Mode(AngleUnit),
+    Format(DisplayMode),
+}
+
+/// Command names offered for completion.
+pub const COMMANDS: [&str; 15] = [
+    ":help", ":quit", ":vars", ":funcs", ":del", ":reset", ":set", ":mode", ":load", ":save",
+    ":history", ":time", "help", "exit", "quit",
+];
+
+/// Setting names offered for completion after `:set`.
+pub const SETTINGS: [&str; 3] = ["fix", "mode", "format"];
+
+/// Usage lines printed by `:help`.
+const USAGE: [(&str, &str); 12] = [
+    (
+        ":help [NAME]",
+        "show this help, or the documentation of NAME",
+    ),
+    (":quit", "leave the REPL (also `exit` or `quit`)"),
+    (":vars", "list user-defined variables"),
+    (":funcs", "list user-defined functions"),
+    (":del NAME", "delete a user-defined variable or function"),
+    (
+        ":reset",
+        "delete every user definition not made by init.evar",
+    ),
+    (
+        ":set [fix N|none | mode rad|deg|grad]",
+        "change or show settings",
+    ),
+    (
+        ":set format dec|hex|bin|oct|sci|eng|si",
+        "print results in another format",
+    ),
+    (":load FILE", "run a script"),
+    (":save FILE", "write this session's definitions to a script"),
+    (":history", "show the REPL history"),
+    (
+        ":time EXPR",
+        "evaluate an expression and report how long it took",
+    ),
+];
+
+/// The command list shown by `:help`, one line per command.
+pub fn usage() -> Vec<String> {
+    let commands = USAGE
+        .iter()
+        .map(|(usage, description)| format!("  {:<40}{}", usage, description));
+    std::iter::once(String::from("commands:"))
+        .chain(commands)
+        .chain([String::new()])
+        .collect()
+}
+
+/// Whether `input` is a command rather than a statement.
+pub fn is_command(input: &str) -> bool {
+    let input = input.trim();
+    input.starts_with(':')
+        || matches!(input, "exit" | "quit")
+        || input
+            .strip_prefix("help")
+            .is_some_and(|rest| rest.is_empty() || rest.starts_with(' '))
+}
+
+pub fn parse_command(input: &str) -> Result<Command, Vec<ParseError>> {
+    let start = input.len() - input.trim_start().len();
+    let name = input[start..].split_whitespace().next().unwrap_or_default();
+    if !COMMANDS.contains(&name) {
+        return Err(vec![ParseError {
+            message: format!("unknown command `{}`, type `:help` for a list", name),
+            span: start..start + name.len(),
+            contexts: Vec::new(),
+        }]);
+    }
+
+    parser()
+        .parse(input)
+        .into_result()
+        .map_err(|errs| errs.into_iter().map(ParseError::from).collect())
+}
+
+fn parser<'a>() -> impl Parser<'a, &'a str, Command, extra::Err<Rich<'a, char>>> {
+    let space = text::inline_whitespace().at_least(1);
+
+    let rest = any()
+        .repeated()
+        .at_least(1)
+        .to_slice()
+        .map(|rest: &str| rest.trim_end().to_string());
+
+    let angle_unit = text::ident()
+        .try_map(|unit: &str, span| unit.parse().map_err(|e| Rich::custom(span, e)))
+        .labelled("angle unit");
+
+    let display_mode = text::ident()
+        .labelled("display mode")
+        .try_map(|mode: &str, span| mode.parse().map_err(|e| Rich::custom(span, e)));
+
+    let fix = text::keyword("none")
+        .to(None)
+        .or(text::int(10).try_map(|n: &str, span| match n.parse() {
+            Ok(n) if n < 64 => Ok(Some(n)),
+            _ => Err(Rich::custom(span, "fix must be in range 0-63")),
+        }))
+        .labelled("number of decimal places");
+
+    let setting = choice((
+        text::keyword("fix")
+            .ignore_then(space)
+            .ignore_then(fix)
+            .map(Setting::Fix),
+        text::keyword("mode")
+            .ignore_then(space)
+            .ignore_then(angle_unit)
+            .map(Setting::Mode),
+        text::keyword("format")
+            .ignore_then(space)
+            .ignore_then(display_mode)
+            .map(Setting::Format),
+    ))
+    .labelled("setting");
+
+    let help = text::keyword("help")
+        .ignore_then(space.ignore_then(text::ident().labelled("name")).or_not())
+        .map(|name: Option<&str>| Command::Help(name.map(str::to_string)));
+
+    let command = choice((
+        help.clone(),
+        text::keyword("quit")
+            .or(text::keyword("exit"))
+            .to(Command::Quit),
+        text::keyword("vars").to(Command::Vars),
+        text::keyword("funcs").to(Command::Funcs),
+        text::keyword("del")
+            .ignore_then(space)
+            .ignore_then(text::ident().labelled("name"))
+            .map(|name: &str| Command::Del(name.to_string())),
+        text::keyword("reset").to(Command::Reset),
+        text::keyword("set")
+            .ignore_then(space.ignore_then(setting).or_not())
+            .map(Command::Set),
+        text::keyword("mode")
+            .ignore_then(space.ignore_then(angle_unit).or_not())
+            .map(|mode| Command::Set(mode.map(Setting::Mode))),
+        text::keyword("load")
+            .ignore_then(space)
+            .ignore_then(rest.labelled("file"))
+            .map(Command::Load),
+        text::keyword("save")
+            .ignore_then(space)
+            .ignore_then(rest.labelled("file"))
+            .map(Command::Save),
+        text::keyword("history").to(Command::History),
+        text::keyword("time")
+            .ignore_then(space)
+            .ignore_then(rest.labelled("expression"))
+            .map(Command::Time),
+    ))
+    .labelled("command");
+
+    just(':')
+        .ignore_then(command)
+        .or(choice((
+            help,
+            text::keyword("exit")
+                .or(text::keyword("quit"))
+                .to(Command::Quit),
+        )))
+        .padded()
+        .then_ignore(end())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_command() {
+        assert_eq!(parse_command("help"), Ok(Command::Help(None)));
+        assert_eq!(
+            parse_command("help sin"),
+            Ok(Command::Help(Some(String::from("sin"))))
+        );
+        assert_eq!(
+            parse_command(":help f"),
+            Ok(Command::Help(Some(String::from("f"))))
+        );
+        assert_eq!(parse_command(" :quit "), Ok(Command::Quit));
+        assert_eq!(parse_command(":vars"), Ok(Command::Vars));
+        assert_eq!(parse_command(":del x"), Ok(Command::Del(String::from("x"))));
+        assert_eq!(
+            parse_command(":set fix 3"),
+            Ok(Command::Set(Some(Setting::Fix(Some(3)))))
+        );
+        assert_eq!(
+            parse_command(":set fix none"),
+            Ok(Command::Set(Some(Setting::Fix(None))))
+        );
+        assert_eq!(
+            parse_command(":set mode deg"),
+            Ok(Command::Set(Some(Setting::Mode(AngleUnit::Degrees))))
+        );
+        assert_eq!(
+            parse_command(":mode grad"),
+            Ok(Command::Set(Some(Setting::Mode(AngleUnit::Gradians))))
+        );
+        assert_eq!(
+            parse_command(":set format hex16"),
+            Ok(Command::Set(Some(Setting::Format(
+                "hex16".parse().unwrap()
+            ))))
+        );
+        assert_eq!(parse_command(":set"), Ok(Command::Set(None)));
+        assert_eq!(
+            parse_command(":load my file.evar"),
+            Ok(Command::Load(String::from("my file.evar")))
+        );
+        assert_eq!(
+            parse_command(":time fib(20) + 1"),
+            Ok(Command::Time(String::from("fib(20) + 1")))
+        );
+
+        assert_eq!(parse_command(" :foo 1").unwrap_err()[0].span, 1..5);
+        assert!(parse_command(":variables").is_err());
+        assert!(parse_command(":del").is_err());
+        assert!(parse_command(":set fix 64").is_err());
+        assert!(parse_command(":set mode turns").is_err());
+        assert!(parse_command(":set format roman").is_err());
+        assert!(parse_command(":vars x").is_err());
+        assert!(parse_command("help sin cos").is_err());
+
+        assert!(is_command("help sin"));
+        assert!(!is_command("helpful + 1"));
+    }
+}
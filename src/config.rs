@@ -0,0 +1,107 @@
+use crate::theme::ThemeColors;
+use directories::ProjectDirs;
+use evar::models::{AngleUnit, DisplayMode};
+use serde::{Deserialize, Deserializer, de::Error};
+use std::{fmt::Display, path::PathBuf, str::FromStr};
+
+/// Defaults read from `config.toml` in the config directory. Flags given on
+/// the command line take precedence.
+#[derive(Debug, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    #[serde(deserialize_with = "fix")]
+    pub fix: Option<usize>,
+    pub no_color: bool,
+    #[serde(deserialize_with = "from_str")]
+    pub angle_unit: Option<AngleUnit>,
+    #[serde(deserialize_with = "from_str")]
+    pub format: Option<DisplayMode>,
+    pub prompt: Option<String>,
+    pub theme: Option<String>,
+    pub colors: ThemeColors,
+}
+
+impl Config {
+    /// Reads the config file. A missing file gives the defaults; an invalid
+    /// one is reported and ignored.
+    pub fn load() -> Config {
+        let path = config_dir().join("config.toml");
+        let Ok(contents) = std::fs::read_to_string(&path) else {
+            return Config::default();
+        };
+        toml::from_str(&contents).unwrap_or_else(|e| {
+            eprintln!("invalid config {}: {}", path.display(), e);
+            Config::default()
+        })
+    }
+}
+
+/// Directory holding `config.toml` and the `init.evar` rc file, e.g.
+/// `~/.config/seva` on Linux.
+pub fn config_dir() -> PathBuf {
+    let seva_dirs =
+        ProjectDirs::from("", "enklht", "seva").expect("no valid home directory path retrieved");
+    PathBuf::from(seva_dirs.config_dir())
+}
+
+fn fix<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<usize>, D::Error> {
+    match Option::<usize>::deserialize(deserializer)? {
+        Some(fix) if fix >= 64 => Err(D::Error::custom("fix must be in range 0-63")),
+        fix => Ok(fix),
+    }
+}
+
+fn from_str<'de, D, T>(deserializer: D) -> Result<Option<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: FromStr,
+    T::Err: Display,
+{
+    Option::<String>::deserialize(deserializer)?
+        .map(|s| s.parse().map_err(D::Error::custom))
+        .transpose()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_config() {
+        let config: Config = toml::from_str(
+            r#"
+            fix = 3
+            no_color = true
+            angle_unit = "deg"
+            format = "hex32"
+            prompt = "evar> "
+            theme = "light"
+
+            [colors]
+            keyword = "magenta"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(
+            config,
+            Config {
+                fix: Some(3),
+                no_color: true,
+                angle_unit: Some(AngleUnit::Degrees),
+                format: Some("hex32".parse().unwrap()),
+                prompt: Some(String::from("evar> ")),
+                theme: Some(String::from("light")),
+                colors: ThemeColors {
+                    keyword: Some(colored::Color::Magenta),
+                    ..Default::default()
+                },
+            }
+        );
+
+        assert_eq!(toml::from_str::<Config>("").unwrap(), Config::default());
+        assert!(toml::from_str::<Config>("fix = 64").is_err());
+        assert!(toml::from_str::<Config>("angle_unit = \"turns\"").is_err());
+        assert!(toml::from_str::<Config>("format = \"roman\"").is_err());
+        assert!(toml::from_str::<Config>("colour = true").is_err());
+    }
+}
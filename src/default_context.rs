@@ -1,96 +1,223 @@
 use crate::{
-    args::AngleUnit,
-    models::{Context, EvalError, Function, Variable},
+    models::{AngleUnit, Context, Doc, EvalError, Function, Value, Variable},
+    units::create_units,
 };
+use num_complex::Complex64;
 use std::collections::HashMap;
 
+macro_rules! doc {
+    ($signature:expr, $domain:expr, $description:expr) => {
+        Doc {
+            signature: $signature,
+            domain: $domain,
+            description: $description,
+        }
+    };
+}
+
 macro_rules! unary_fn {
-    ($fname:expr, $body:expr) => {
-        (String::from($fname), Function::new_external(1, $body))
+    ($fname:expr, $doc:expr, $body:expr) => {
+        (
+            String::from($fname),
+            Function::new_external(1, $body).with_doc($doc),
+        )
+    };
+}
+
+macro_rules! angle_fn {
+    ($fname:expr, $doc:expr, $body:expr) => {
+        (
+            String::from($fname),
+            Function::new_external_with_context(1, $body).with_doc($doc),
+        )
     };
 }
 
 macro_rules! binary_fn {
-    ($fname:expr, $body:expr) => {
-        (String::from($fname), Function::new_external(2, $body))
+    ($fname:expr, $doc:expr, $body:expr) => {
+        (
+            String::from($fname),
+            Function::new_external(2, $body).with_doc($doc),
+        )
     };
 }
 
+/// Applies `real` to real arguments and `complex` to complex ones.
+fn real_or_complex(
+    x: &Value,
+    real: fn(f64) -> f64,
+    complex: fn(Complex64) -> Complex64,
+) -> Result<Value, EvalError> {
+    if x.is_complex() {
+        Ok(complex(x.to_complex()?).into())
+    } else {
+        Ok(real(x.to_float()?).into())
+    }
+}
+
+/// Converts an angle to radians. Plain numbers are taken to be in the
+/// context's angle unit.
+fn to_radians(x: &Value, context: &Context) -> Result<Value, EvalError> {
+    match x.angle_in_radians() {
+        Some(radians) => Ok(radians.into()),
+        None => x.clone() * context.angle_unit().radians().into(),
+    }
+}
+
+/// Converts an angle in radians to a quantity in the context's angle unit.
+fn from_radians(x: f64, context: &Context) -> Value {
+    let angle_unit = context.angle_unit();
+    let unit = context
+        .get_unit(&angle_unit.to_string())
+        .expect("angle units are in the unit table");
+    Value::quantity(angle_unit.from_radians(x), unit)
+}
+
+fn sqrt(x: &Value, promote: bool) -> Result<Value, EvalError> {
+    if x.is_complex() {
+        return Ok(x.to_complex()?.sqrt().into());
+    }
+    match x.to_float()? {
+        n if 0. <= n => Ok(n.sqrt().into()),
+        n if promote => Ok(Complex64::from(n).sqrt().into()),
+        _ => Err(EvalError::MathDomain(
+            "the domain of sqrt is [0, infinity)".to_string(),
+        )),
+    }
+}
+
+fn ln(x: &Value, promote: bool) -> Result<Value, EvalError> {
+    if x.is_complex() {
+        return Ok(x.to_complex()?.ln().into());
+    }
+    match x.to_float()? {
+        n if 0. < n => Ok(n.ln().into()),
+        n if promote && n < 0. => Ok(Complex64::from(n).ln().into()),
+        _ => Err(EvalError::MathDomain(
+            "the domain of ln is (0, infinity)".to_string(),
+        )),
+    }
+}
+
+fn log(x: &Value, base: &Value, promote: bool) -> Result<Value, EvalError> {
+    if x.is_complex() || base.is_complex() {
+        return Ok((x.to_complex()?.ln() / base.to_complex()?.ln()).into());
+    }
+    match x.to_float()? {
+        n if 0. < n => Ok(n.log(base.to_float()?).into()),
+        n if promote && n < 0. => Ok((Complex64::from(n).ln() / base.to_complex()?.ln()).into()),
+        _ => Err(EvalError::MathDomain(
+            "the domain of log is (0, infinity) x R".to_string(),
+        )),
+    }
+}
+
+const ANGLE: &str = "any angle; plain numbers are in the current angle unit";
+const REAL: &str = "all reals";
+const COMPLEX: &str = "all complex numbers";
+
 #[rustfmt::skip]
-pub fn create_context(angle_unit: &AngleUnit) -> Context {
-    let mut functions = HashMap::from(match angle_unit {
-        AngleUnit::Radian => [
-            unary_fn!("sin", |x| Ok(x[0].to_float()?.sin().into())),
-            unary_fn!("cos", |x| Ok(x[0].to_float()?.cos().into())),
-            unary_fn!("tan", |x| Ok(x[0].to_float()?.tan().into())),
-            unary_fn!("sec", |x| Ok(x[0].to_float()?.sin().recip().into())),
-            unary_fn!("csc", |x| Ok(x[0].to_float()?.cos().recip().into())),
-            unary_fn!("cot", |x| Ok(x[0].to_float()?.tan().recip().into())),
-            unary_fn!("asin", |x| match x[0].to_float()? {
-                n if (-1. ..=1.).contains(&n) => Ok(n.asin().into()),
-                _ => Err(EvalError::MathDomain("the domain of asin is [-1, 1]".to_string()))
-            }),
-            unary_fn!("acos", |x| match x[0].to_float()? {
-                n if (-1. ..=1.).contains(&n) => Ok(n.acos().into()),
+pub fn create_context(angle_unit: &AngleUnit, complex: bool) -> Context {
+    let mut functions = HashMap::from([
+        angle_fn!("sin", doc!("sin(x)", ANGLE, "Sine of x."),
+            |x, context| real_or_complex(&to_radians(&x[0], context)?, f64::sin, Complex64::sin)),
+        angle_fn!("cos", doc!("cos(x)", ANGLE, "Cosine of x."),
+            |x, context| real_or_complex(&to_radians(&x[0], context)?, f64::cos, Complex64::cos)),
+        angle_fn!("tan", doc!("tan(x)", ANGLE, "Tangent of x."),
+            |x, context| real_or_complex(&to_radians(&x[0], context)?, f64::tan, Complex64::tan)),
+        angle_fn!("sec", doc!("sec(x)", ANGLE, "Secant of x, 1 / cos(x)."),
+            |x, context| Ok(to_radians(&x[0], context)?.to_float()?.cos().recip().into())),
+        angle_fn!("csc", doc!("csc(x)", ANGLE, "Cosecant of x, 1 / sin(x)."),
+            |x, context| Ok(to_radians(&x[0], context)?.to_float()?.sin().recip().into())),
+        angle_fn!("cot", doc!("cot(x)", ANGLE, "Cotangent of x, 1 / tan(x)."),
+            |x, context| Ok(to_radians(&x[0], context)?.to_float()?.tan().recip().into())),
+        angle_fn!("asin", doc!("asin(x)", "[-1, 1]", "Arcsine of x, as an angle in the current angle unit."),
+            |x, context| match x[0].to_float()? {
+                n if (-1. ..=1.).contains(&n) => Ok(from_radians(n.asin(), context)),
                 _ => Err(EvalError::MathDomain("the domain of asin is [-1, 1]".to_string()))
             }),
-            unary_fn!("atan", |x| Ok(x[0].to_float()?.atan().into())),
-        ],
-        AngleUnit::Degrees => [
-            unary_fn!("sin", |x| Ok(x[0].to_float()?.to_radians().sin().into())),
-            unary_fn!("cos", |x| Ok(x[0].to_float()?.to_radians().cos().into())),
-            unary_fn!("tan", |x| Ok(x[0].to_float()?.to_radians().tan().into())),
-            unary_fn!("sec", |x| Ok(x[0].to_float()?.to_radians().sin().recip().into())),
-            unary_fn!("csc", |x| Ok(x[0].to_float()?.to_radians().cos().recip().into())),
-            unary_fn!("cot", |x| Ok(x[0].to_float()?.to_radians().tan().recip().into())),
-            unary_fn!("asin", |x| match x[0].to_float()? {
-                n if (-1. ..=1.).contains(&n) => Ok(n.asin().to_degrees().into()),
-                _ => Err(EvalError::MathDomain("the domain of asin is [-1, 1]".to_string()))
+        angle_fn!("acos", doc!("acos(x)", "[-1, 1]", "Arccosine of x, as an angle in the current angle unit."),
+            |x, context| match x[0].to_float()? {
+                n if (-1. ..=1.).contains(&n) => Ok(from_radians(n.acos(), context)),
+                _ => Err(EvalError::MathDomain("the domain of acos is [-1, 1]".to_string()))
             }),
-            unary_fn!("acos", |x| match x[0].to_float()? {
-                n if (-1. ..=1.).contains(&n) => Ok(n.acos().to_degrees().into()),
-                _ => Err(EvalError::MathDomain("the domain of asin is [-1, 1]".to_string()))
-            }),
-            unary_fn!("atan", |x| Ok(x[0].to_float()?.atan().to_degrees().into())),
-        ],
-    });
+        angle_fn!("atan", doc!("atan(x)", REAL, "Arctangent of x, as an angle in the current angle unit."),
+            |x, context| Ok(from_radians(x[0].to_float()?.atan(), context))),
+    ]);
+
+    let promoted = if complex {
+        [
+            unary_fn!("sqrt", doc!("sqrt(x)", COMPLEX, "Principal square root of x."),
+                |x| sqrt(&x[0], true)),
+            unary_fn!("ln", doc!("ln(x)", "all complex numbers except 0", "Natural logarithm of x."),
+                |x| ln(&x[0], true)),
+            binary_fn!("log", doc!("log(x, b)", "(C \\ {0}) x C", "Base b logarithm of x."),
+                |x| log(&x[0], &x[1], true)),
+        ]
+    } else {
+        [
+            unary_fn!("sqrt", doc!("sqrt(x)", "[0, infinity)", "Square root of x."),
+                |x| sqrt(&x[0], false)),
+            unary_fn!("ln", doc!("ln(x)", "(0, infinity)", "Natural logarithm of x."),
+                |x| ln(&x[0], false)),
+            binary_fn!("log", doc!("log(x, b)", "(0, infinity) x R", "Base b logarithm of x."),
+                |x| log(&x[0], &x[1], false)),
+        ]
+    };
+    functions.extend(promoted);
 
     for (name, function) in [
-        unary_fn!("sinh", |x| Ok(x[0].to_float()?.sinh().into())),
-        unary_fn!("cosh", |x| Ok(x[0].to_float()?.cosh().into())),
-        unary_fn!("tanh", |x| Ok(x[0].to_float()?.tanh().into())),
-        unary_fn!("sqrt", |x| match x[0].to_float()? {
-            n if 0. <= n => Ok(n.sqrt().into()),
-            _ => Err(EvalError::MathDomain("the domain of sqrt is [0, infinity)".to_string()))
-        }),
-        unary_fn!("exp", |x| Ok(x[0].to_float()?.exp().into())),
-        unary_fn!("exp2", |x| Ok(x[0].to_float()?.exp2().into())),
-        unary_fn!("ln", |x| match x[0].to_float()? {
-            n if 0. < n => Ok(n.ln().into()),
-            _ => Err(EvalError::MathDomain("the domain of ln is (0, infinity)".to_string()))
-        }),
-        unary_fn!("log2", |x| match x[0].to_float()? {
-            n if 0. < n => Ok(n.log2().into()),
-            _ => Err(EvalError::MathDomain("the domain of log2 is (0, infinity)".to_string()))
-        }),
-        unary_fn!("log10", |x| match x[0].to_float()? {
-            n if 0. < n => Ok(n.log10().into()),
-            _ => Err(EvalError::MathDomain("the domain of log10 is (0, infinity)".to_string()))
-        }),
-        unary_fn!("rad", |x| Ok(x[0].to_float()?.to_radians().into())),
-        unary_fn!("deg", |x| Ok(x[0].to_float()?.to_degrees().into())),
-        unary_fn!("floor", |x| Ok((x[0].to_float()?.floor() as i32).into())),
-        unary_fn!("ceil", |x| Ok((x[0].to_float()?.ceil() as i32).into())),
-        unary_fn!("round", |x| Ok((x[0].to_float()?.round() as i32).into())),
-        unary_fn!("abs", |x| Ok(x[0].abs())),
-        binary_fn!("nroot", |x| match (x[0].to_float()?, x[1].to_float()?) {
-            (x, n) if 0. <= x && n != 0. => Ok(x.powf(n.recip()).into()),
-            _ => Err(EvalError::MathDomain("the domain of nroot is [0, infinity) x (R \\ {0})".to_string()))
-        }),
-        binary_fn!("log", |x| match x[0].to_float()? {
-            n if 0. < n => Ok(n.log(x[1].to_float()?).into()),
-            _ => Err(EvalError::MathDomain("the domain of log10 is (0, infinity) x R".to_string()))
-        }),
+        unary_fn!("sinh", doc!("sinh(x)", REAL, "Hyperbolic sine of x."),
+            |x| Ok(x[0].to_float()?.sinh().into())),
+        unary_fn!("cosh", doc!("cosh(x)", REAL, "Hyperbolic cosine of x."),
+            |x| Ok(x[0].to_float()?.cosh().into())),
+        unary_fn!("tanh", doc!("tanh(x)", REAL, "Hyperbolic tangent of x."),
+            |x| Ok(x[0].to_float()?.tanh().into())),
+        unary_fn!("exp", doc!("exp(x)", COMPLEX, "e raised to the power x."),
+            |x| real_or_complex(&x[0], f64::exp, Complex64::exp)),
+        unary_fn!("exp2", doc!("exp2(x)", REAL, "2 raised to the power x."),
+            |x| Ok(x[0].to_float()?.exp2().into())),
+        unary_fn!("log2", doc!("log2(x)", "(0, infinity)", "Base 2 logarithm of x."),
+            |x| match x[0].to_float()? {
+                n if 0. < n => Ok(n.log2().into()),
+                _ => Err(EvalError::MathDomain("the domain of log2 is (0, infinity)".to_string()))
+            }),
+        unary_fn!("log10", doc!("log10(x)", "(0, infinity)", "Base 10 logarithm of x."),
+            |x| match x[0].to_float()? {
+                n if 0. < n => Ok(n.log10().into()),
+                _ => Err(EvalError::MathDomain("the domain of log10 is (0, infinity)".to_string()))
+            }),
+        unary_fn!("rad", doc!("rad(x)", REAL, "Converts x degrees to radians."),
+            |x| Ok(x[0].to_float()?.to_radians().into())),
+        unary_fn!("deg", doc!("deg(x)", REAL, "Converts x radians to degrees."),
+            |x| Ok(x[0].to_float()?.to_degrees().into())),
+        unary_fn!("floor", doc!("floor(x)", REAL, "Largest integer not greater than x."),
+            |x| x[0].floor()),
+        unary_fn!("ceil", doc!("ceil(x)", REAL, "Smallest integer not less than x."),
+            |x| x[0].ceil()),
+        unary_fn!("round", doc!("round(x)", REAL, "Nearest integer to x, rounding half away from zero."),
+            |x| x[0].round()),
+        unary_fn!("abs", doc!("abs(x)", COMPLEX, "Absolute value, or modulus, of x."),
+            |x| x[0].abs()),
+        unary_fn!("re", doc!("re(z)", COMPLEX, "Real part of z."),
+            |x| x[0].re()),
+        unary_fn!("im", doc!("im(z)", COMPLEX, "Imaginary part of z."),
+            |x| x[0].im()),
+        unary_fn!("arg", doc!("arg(z)", COMPLEX, "Argument of z in radians, in (-pi, pi]."),
+            |x| x[0].arg()),
+        unary_fn!("conj", doc!("conj(z)", COMPLEX, "Complex conjugate of z."),
+            |x| x[0].conj()),
+        unary_fn!("float", doc!("float(x)", REAL, "x as a floating point number."),
+            |x| Ok(x[0].to_float()?.into())),
+        unary_fn!("frac", doc!("frac(x)", REAL, "x as an exact fraction."),
+            |x| Ok(x[0].to_rational()?.into())),
+        binary_fn!("pow", doc!("pow(x, y)", COMPLEX, "x raised to the power y, the same as x ^ y."),
+            move |x| x[0].clone().pow(x[1].clone(), complex)),
+        binary_fn!("nroot", doc!("nroot(x, n)", "[0, infinity) x (R \\ {0})", "The nth root of x."),
+            |x| match (x[0].to_float()?, x[1].to_float()?) {
+                (x, n) if 0. <= x && n != 0. => Ok(x.powf(n.recip()).into()),
+                _ => Err(EvalError::MathDomain("the domain of nroot is [0, infinity) x (R \\ {0})".to_string()))
+            }),
     ] {
         functions.insert(name, function);
     }
@@ -100,8 +227,14 @@ pub fn create_context(angle_unit: &AngleUnit) -> Context {
         (String::from("e"), Variable::External(E.into())),
         (String::from("pi"), Variable::External(PI.into())),
         (String::from("tau"), Variable::External(TAU.into())),
+        (String::from("i"), Variable::External(Complex64::i().into())),
+        (String::from("true"), Variable::External(true.into())),
+        (String::from("false"), Variable::External(false.into())),
     ]
     .into();
 
-    Context::new(functions, variables)
+    let mut context = Context::new(functions, variables, create_units());
+    context.set_angle_unit(*angle_unit);
+    context.set_complex(complex);
+    context
 }
@@ -1,21 +1,74 @@
-use crate::models::Token;
-use chumsky::prelude::*;
+use crate::theme::Theme;
 use codespan_reporting::{
     diagnostic::{Diagnostic, Label},
-    files::SimpleFile,
+    files::{Error as FilesError, Files, SimpleFile},
     term::{
-        Config, emit_to_write_style,
+        Config, Styles, StylesWriter, emit_to_write_style,
         termcolor::{ColorChoice, StandardStream},
     },
 };
+use evar::models::{EvalError, ParseError};
+use std::ops::Range;
+
+/// Number of calls shown in the backtrace of an evaluation error.
+const MAX_FRAMES: usize = 8;
+
+/// Where an input line comes from: the file name and its line number.
+#[derive(Clone, Copy)]
+pub struct Location<'a> {
+    pub name: &'a str,
+    pub line: usize,
+}
+
+impl Location<'_> {
+    pub fn repl() -> Location<'static> {
+        Location {
+            name: "<repl>",
+            line: 1,
+        }
+    }
+}
+
+/// A single line of input that reports the line number it has in its file.
+struct SourceLine<'a> {
+    file: SimpleFile<&'a str, &'a str>,
+    line: usize,
+}
+
+impl<'a> Files<'a> for SourceLine<'a> {
+    type FileId = ();
+    type Name = &'a str;
+    type Source = &'a str;
+
+    fn name(&'a self, id: ()) -> Result<&'a str, FilesError> {
+        Files::name(&self.file, id)
+    }
+
+    fn source(&'a self, id: ()) -> Result<&'a str, FilesError> {
+        Files::source(&self.file, id)
+    }
+
+    fn line_index(&'a self, id: (), byte_index: usize) -> Result<usize, FilesError> {
+        self.file.line_index(id, byte_index)
+    }
+
+    fn line_number(&'a self, _id: (), line_index: usize) -> Result<usize, FilesError> {
+        Ok(self.line + line_index)
+    }
+
+    fn line_range(&'a self, id: (), line_index: usize) -> Result<Range<usize>, FilesError> {
+        self.file.line_range(id, line_index)
+    }
+}
 
 pub struct ErrorReporter {
     writer: StandardStream,
     config: Config,
+    styles: Styles,
 }
 
 impl ErrorReporter {
-    pub fn new(no_color: bool) -> Self {
+    pub fn new(no_color: bool, theme: &Theme) -> Self {
         let writer = StandardStream::stderr(if no_color {
             ColorChoice::Never
         } else {
@@ -24,28 +77,75 @@ impl ErrorReporter {
         ErrorReporter {
             writer,
             config: codespan_reporting::term::Config::default(),
+            styles: theme.styles(),
         }
     }
 
-    pub fn report_error(&mut self, errs: Vec<Rich<'_, Token<'_>>>, input: &str) {
-        let file = SimpleFile::new("<repl>", input);
-
+    pub fn report_error(&mut self, errs: &[ParseError], input: &str, location: Location<'_>) {
         for err in errs {
-            let mut labels = vec![
-                Label::primary((), err.span().into_range()).with_message(err.reason().to_string()),
-            ];
+            let mut labels =
+                vec![Label::primary((), err.span.clone()).with_message(err.message.clone())];
 
-            labels.extend(err.contexts().map(|(label, span)| {
-                Label::secondary((), span.into_range())
-                    .with_message(format!("while partang this {}", label))
+            labels.extend(err.contexts.iter().map(|(label, span)| {
+                Label::secondary((), span.clone())
+                    .with_message(format!("while parsing this {}", label))
             }));
 
             let diagnostic = Diagnostic::error()
-                .with_message(err.reason().to_string())
+                .with_message(err.message.clone())
                 .with_labels(labels);
 
-            emit_to_write_style(&mut self.writer.lock(), &self.config, &file, &diagnostic)
-                .expect("failed writing diagnostics");
+            self.emit(&diagnostic, input, location);
         }
     }
+
+    pub fn report_eval_error(&mut self, err: &EvalError, input: &str, location: Location<'_>) {
+        let label = match err.span() {
+            Some(span) => Label::primary((), span).with_message(err.to_string()),
+            None => Label::primary((), line_span(input)),
+        };
+        let trace = err.trace();
+        let mut notes = trace
+            .iter()
+            .take(MAX_FRAMES)
+            .map(ToString::to_string)
+            .collect::<Vec<_>>();
+        if trace.len() > MAX_FRAMES {
+            notes.push(format!("... and {} more calls", trace.len() - MAX_FRAMES));
+        }
+        notes.extend(err.help().map(|help| format!("help: {}", help)));
+
+        let diagnostic = Diagnostic::error()
+            .with_message(err.to_string())
+            .with_labels(vec![label])
+            .with_notes(notes);
+
+        self.emit(&diagnostic, input, location);
+    }
+
+    /// Reports an error that applies to the whole input line.
+    pub fn report_command_error(&mut self, message: &str, input: &str, location: Location<'_>) {
+        let diagnostic = Diagnostic::error()
+            .with_message(message)
+            .with_labels(vec![Label::primary((), line_span(input))]);
+
+        self.emit(&diagnostic, input, location);
+    }
+
+    fn emit(&mut self, diagnostic: &Diagnostic<()>, input: &str, location: Location<'_>) {
+        let file = SourceLine {
+            file: SimpleFile::new(location.name, input),
+            line: location.line,
+        };
+
+        let mut writer = StylesWriter::new(self.writer.lock(), &self.styles);
+        emit_to_write_style(&mut writer, &self.config, &file, diagnostic)
+            .expect("failed writing diagnostics");
+    }
+}
+
+/// Span of the input line without surrounding whitespace.
+pub fn line_span(input: &str) -> Range<usize> {
+    let start = input.len() - input.trim_start().len();
+    start..input.trim_end().len()
 }
@@ -0,0 +1,265 @@
+use crate::error_report::{Location, line_span};
+use evar::{
+    Error,
+    models::{DisplayMode, Frame, ParseError, Value},
+};
+use serde::Serialize;
+use std::ops::Range;
+
+/// Result of one evaluated statement in `--json` mode.
+#[derive(Serialize)]
+pub struct StatementReport<'a> {
+    pub input: &'a str,
+    pub file: &'a str,
+    pub line: usize,
+    pub value: Option<String>,
+    #[serde(rename = "type")]
+    pub type_name: Option<String>,
+    pub errors: Vec<ErrorReport>,
+}
+
+/// Output of one REPL command in `--json` mode.
+#[derive(Serialize)]
+pub struct CommandReport<'a> {
+    pub input: &'a str,
+    pub file: &'a str,
+    pub line: usize,
+    pub output: Vec<String>,
+    pub errors: Vec<ErrorReport>,
+}
+
+#[derive(Serialize)]
+pub struct ErrorReport {
+    pub kind: &'static str,
+    pub message: String,
+    pub span: Span,
+    /// A suggestion such as a similarly named function.
+    pub help: Option<String>,
+    /// Calls to user-defined functions the error unwound through, innermost
+    /// first.
+    pub trace: Vec<FrameReport>,
+}
+
+#[derive(Serialize)]
+pub struct FrameReport {
+    pub function: String,
+    pub args: Vec<ArgReport>,
+    pub expr: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct ArgReport {
+    pub name: String,
+    pub value: String,
+}
+
+impl From<&Frame> for FrameReport {
+    fn from(frame: &Frame) -> Self {
+        FrameReport {
+            function: frame.function.clone(),
+            args: frame
+                .args
+                .iter()
+                .map(|(name, value)| ArgReport {
+                    name: name.clone(),
+                    value: value.to_string(),
+                })
+                .collect(),
+            expr: frame.expr.clone(),
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+impl ErrorReport {
+    fn syntax(err: &ParseError) -> Self {
+        ErrorReport {
+            kind: "SyntaxError",
+            message: err.message.clone(),
+            span: err.span.clone().into(),
+            help: None,
+            trace: Vec::new(),
+        }
+    }
+}
+
+impl From<Range<usize>> for Span {
+    fn from(range: Range<usize>) -> Self {
+        Span {
+            start: range.start,
+            end: range.end,
+        }
+    }
+}
+
+impl<'a> StatementReport<'a> {
+    pub fn new(
+        input: &'a str,
+        location: Location<'a>,
+        result: &Result<Value, Error>,
+        fix: Option<usize>,
+        display_mode: DisplayMode,
+    ) -> Self {
+        let (value, type_name, errors) = match result {
+            Ok(value) => (
+                (!value.is_null()).then(|| value.format(fix, display_mode)),
+                Some(value.type_name()),
+                Vec::new(),
+            ),
+            Err(Error::ParseError(errs)) => {
+                (None, None, errs.iter().map(ErrorReport::syntax).collect())
+            }
+            Err(Error::EvalError(err)) => {
+                let span = err.span().unwrap_or_else(|| line_span(input));
+                let error = ErrorReport {
+                    kind: err.kind(),
+                    message: err.to_string(),
+                    span: span.into(),
+                    help: err.help().map(String::from),
+                    trace: err.trace().iter().map(FrameReport::from).collect(),
+                };
+                (None, None, vec![error])
+            }
+        };
+
+        StatementReport {
+            input,
+            file: location.name,
+            line: location.line,
+            value,
+            type_name,
+            errors,
+        }
+    }
+
+    pub fn print(&self) {
+        println!(
+            "{}",
+            serde_json::to_string(self).expect("failed to serialize report")
+        );
+    }
+}
+
+impl<'a> CommandReport<'a> {
+    /// A report of a command's output, or of the message it failed with.
+    pub fn new(
+        input: &'a str,
+        location: Location<'a>,
+        output: Vec<String>,
+        error: Option<&str>,
+    ) -> Self {
+        let errors = error
+            .map(|message| ErrorReport {
+                kind: "CommandError",
+                message: message.to_string(),
+                span: line_span(input).into(),
+                help: None,
+                trace: Vec::new(),
+            })
+            .into_iter()
+            .collect();
+        CommandReport {
+            input,
+            file: location.name,
+            line: location.line,
+            output,
+            errors,
+        }
+    }
+
+    /// A report of a command that did not parse.
+    pub fn syntax_errors(input: &'a str, location: Location<'a>, errs: &[ParseError]) -> Self {
+        CommandReport {
+            input,
+            file: location.name,
+            line: location.line,
+            output: Vec::new(),
+            errors: errs.iter().map(ErrorReport::syntax).collect(),
+        }
+    }
+
+    pub fn print(&self) {
+        println!(
+            "{}",
+            serde_json::to_string(self).expect("failed to serialize report")
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use evar::Evaluator;
+
+    fn report(input: &str) -> String {
+        let result = Evaluator::default().eval(input);
+        serde_json::to_string(&StatementReport::new(
+            input,
+            Location::repl(),
+            &result,
+            None,
+            DisplayMode::Decimal,
+        ))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_json_report() {
+        assert_eq!(
+            report("1/2"),
+            r#"{"input":"1/2","file":"<repl>","line":1,"value":"1/2","type":"Rational","errors":[]}"#
+        );
+        assert_eq!(
+            report(" 1/0"),
+            r#"{"input":" 1/0","file":"<repl>","line":1,"value":null,"type":null,"errors":[{"kind":"DivisionByZero","message":"division by zero","span":{"start":1,"end":4},"help":null,"trace":[]}]}"#
+        );
+        assert!(report("1 +").contains(r#""kind":"SyntaxError""#));
+        assert!(report("sqr(4)").contains(r#""help":"did you mean `sqrt`?""#));
+    }
+
+    #[test]
+    fn test_json_trace() {
+        let mut evaluator = Evaluator::default();
+        evaluator.eval("let f(x) = x / 0").unwrap();
+        let result = evaluator.eval("f(3)");
+        let json = serde_json::to_string(&StatementReport::new(
+            "f(3)",
+            Location::repl(),
+            &result,
+            None,
+            DisplayMode::Decimal,
+        ))
+        .unwrap();
+        assert!(json.contains(
+            r#""trace":[{"function":"f","args":[{"name":"x","value":"3"}],"expr":"x / 0"}]"#
+        ));
+    }
+
+    #[test]
+    fn test_json_command_report() {
+        let report = |output: Vec<&str>, error| {
+            let output = output.into_iter().map(String::from).collect();
+            serde_json::to_string(&CommandReport::new(
+                " :vars",
+                Location::repl(),
+                output,
+                error,
+            ))
+            .unwrap()
+        };
+
+        assert_eq!(
+            report(vec!["x = 2"], None),
+            r#"{"input":" :vars","file":"<repl>","line":1,"output":["x = 2"],"errors":[]}"#
+        );
+        assert_eq!(
+            report(Vec::new(), Some("failed")),
+            r#"{"input":" :vars","file":"<repl>","line":1,"output":[],"errors":[{"kind":"CommandError","message":"failed","span":{"start":1,"end":6},"help":null,"trace":[]}]}"#
+        );
+    }
+}
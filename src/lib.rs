@@ -0,0 +1,406 @@
+//! The evar calculator as a library.
+//!
+//! ```
+//! use evar::Evaluator;
+//!
+//! let mut evaluator = Evaluator::default();
+//! evaluator.register_function("double", 1, |args| args[0].clone() * 2.into());
+//! evaluator.eval("let x = 21").unwrap();
+//! assert_eq!(evaluator.eval("double(x)").unwrap().to_string(), "42");
+//! ```
+
+mod default_context;
+pub mod models;
+pub mod parser;
+mod units;
+
+use chumsky::{
+    input::{Input, Stream},
+    prelude::*,
+};
+pub use default_context::create_context;
+pub use models::Error;
+use models::{
+    AngleUnit, Context, EvalError, Expr, Function, ParseError, Stmt, Token, Value, Variable,
+};
+use parser::parser;
+
+/// Parses one statement, returning every syntax error found.
+pub fn lex_and_parse(input: &str) -> Result<Stmt, Vec<ParseError>> {
+    let token_iter = models::token::lex(input)
+        .filter(|token| !matches!(token, (Token::Space | Token::Comment, _)));
+
+    let token_stream = Stream::from_iter(token_iter)
+        .map((input.len()..input.len()).into(), |(token, span)| {
+            (token, span.into())
+        });
+
+    parser(input)
+        .parse(token_stream)
+        .into_result()
+        .map_err(|errs| errs.into_iter().map(ParseError::from).collect())
+}
+
+/// Number of expressions a preview may evaluate, enough for any hand-typed
+/// calculation but not for runaway recursion.
+pub const PREVIEW_STEPS: usize = 100_000;
+
+/// Evaluates statements against one persistent set of variables and
+/// functions.
+///
+/// Deeply recursive user functions need a large stack; run the evaluator on
+/// a thread with a generous stack size if inputs are not trusted.
+#[derive(Clone)]
+pub struct Evaluator {
+    context: Context,
+}
+
+impl Default for Evaluator {
+    /// An evaluator with the builtin functions and constants, using radians
+    /// and reporting domain errors instead of promoting to complex numbers.
+    fn default() -> Self {
+        Evaluator::new(&AngleUnit::Radian, false)
+    }
+}
+
+impl Evaluator {
+    pub fn new(angle_unit: &AngleUnit, complex: bool) -> Self {
+        Evaluator {
+            context: create_context(angle_unit, complex),
+        }
+    }
+
+    pub fn parse(&self, input: &str) -> Result<Stmt, Error> {
+        lex_and_parse(input).map_err(Error::ParseError)
+    }
+
+    pub fn eval_stmt(&mut self, stmt: Stmt) -> Result<Value, Error> {
+        Ok(stmt.eval(&mut self.context)?)
+    }
+
+    /// Parses and evaluates one statement. Definitions evaluate to the
+    /// defined value, or to a null value for functions.
+    pub fn eval(&mut self, input: &str) -> Result<Value, Error> {
+        let stmt = self.parse(input)?;
+        self.eval_stmt(stmt)
+    }
+
+    /// Evaluates `input` on a copy of the context, so that neither
+    /// definitions nor the previous answer change. A `let` evaluates to its
+    /// right-hand side without defining anything. Gives up with
+    /// [`EvalError::StepLimit`] after [`PREVIEW_STEPS`] expressions.
+    pub fn preview(&self, input: &str) -> Result<Value, Error> {
+        let stmt = self.parse(input)?;
+        Ok(self.preview_stmt(stmt)?)
+    }
+
+    /// Evaluates an already parsed statement like [`Evaluator::preview`].
+    pub fn preview_stmt(&self, stmt: Stmt) -> Result<Value, EvalError> {
+        match stmt {
+            Stmt::Expr(expr) | Stmt::DefVar { expr, .. } => {
+                let mut context = self.context.clone();
+                context.set_step_limit(Some(PREVIEW_STEPS));
+                expr.eval(&mut context)
+            }
+            Stmt::DefFun { .. } => Ok(Value::null()),
+            Stmt::Formatted { stmt, .. } => self.preview_stmt(*stmt),
+        }
+    }
+
+    /// Defines a variable as if by `let`. Returns `false` if `name` is a
+    /// constant.
+    pub fn set_variable(&mut self, name: &str, value: impl Into<Value>) -> bool {
+        self.context.set_variable(name, value.into()).is_some()
+    }
+
+    pub fn define_constant(&mut self, name: &str, value: impl Into<Value>) {
+        self.context.define_constant(name, value.into());
+    }
+
+    pub fn register_function(
+        &mut self,
+        name: &str,
+        arity: usize,
+        body: impl Fn(Vec<Value>) -> Result<Value, EvalError> + 'static,
+    ) {
+        self.context
+            .define_function(name, Function::new_external(arity, body));
+    }
+
+    /// Removes a user-defined variable or function. Returns whether anything
+    /// was removed; builtins and constants cannot be removed.
+    pub fn delete(&mut self, name: &str) -> bool {
+        let variable = self.context.remove_variable(name);
+        let function = self.context.remove_function(name);
+        variable || function
+    }
+
+    /// Writes the user-defined variables and functions as `let` statements
+    /// that recreate them, one per line. Variables whose value cannot be
+    /// written as an expression are left out.
+    pub fn to_script(&self) -> String {
+        self.definitions().concat()
+    }
+
+    /// Like [`Evaluator::to_script`], but leaves out definitions that are the
+    /// same in `base`, such as those made by an init script.
+    pub fn to_script_since(&self, base: &Evaluator) -> String {
+        let base = base.definitions();
+        self.definitions()
+            .into_iter()
+            .filter(|definition| !base.contains(definition))
+            .collect()
+    }
+
+    /// The lines of [`Evaluator::to_script`].
+    fn definitions(&self) -> Vec<String> {
+        let mut variables = self
+            .variables()
+            .filter(|(_, variable)| !variable.is_external())
+            .filter_map(|(name, variable)| Some((name, variable.get().to_source()?)))
+            .collect::<Vec<_>>();
+        variables.sort();
+
+        let mut functions = self
+            .functions()
+            .filter(|(_, function)| !function.is_external())
+            .collect::<Vec<_>>();
+        functions.sort_by_key(|(name, _)| *name);
+
+        let mut definitions = Vec::new();
+        for (name, value) in variables {
+            definitions.push(format!("let {} = {}\n", name, value));
+        }
+        for (name, function) in functions {
+            let arg_names = function.arg_names().unwrap_or_default().join(", ");
+            let body = function.body().map(Expr::to_source).unwrap_or_default();
+            let mut definition = format!("let {}({}) = {}", name, arg_names, body);
+            if let Some(doc) = function.docstring() {
+                definition += &format!(" # {}", doc);
+            }
+            definitions.push(definition + "\n");
+        }
+        definitions
+    }
+
+    pub fn variable(&self, name: &str) -> Option<Value> {
+        self.context
+            .get_variable(name)
+            .map(|variable| variable.get())
+    }
+
+    pub fn function(&self, name: &str) -> Option<&Function> {
+        self.context.get_function(name)
+    }
+
+    pub fn variables(&self) -> impl Iterator<Item = (&String, &Variable)> {
+        self.context.variables()
+    }
+
+    pub fn functions(&self) -> impl Iterator<Item = (&String, &Function)> {
+        self.context.functions()
+    }
+
+    pub fn context(&self) -> &Context {
+        &self.context
+    }
+
+    pub fn context_mut(&mut self) -> &mut Context {
+        &mut self.context
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{cell::Cell, rc::Rc};
+
+    #[test]
+    fn test_eval() {
+        let mut evaluator = Evaluator::default();
+        let half = (Value::from(1) / 2.into()).unwrap();
+        assert_eq!(evaluator.eval("let x = 1/2").unwrap(), half);
+        assert_eq!(evaluator.eval("x * 4").unwrap(), Value::from(2));
+        assert_eq!(evaluator.variable("x"), Some(half));
+        assert!(matches!(
+            evaluator.eval("1 +"),
+            Err(Error::ParseError(errs)) if errs.len() == 1 && errs[0].span == (3..3)
+        ));
+        assert!(matches!(
+            evaluator.eval("1 / 0"),
+            Err(Error::EvalError(err)) if matches!(err.root(), EvalError::DivisionByZero)
+        ));
+    }
+
+    #[test]
+    fn test_error_span() {
+        let mut evaluator = Evaluator::default();
+        let span = |evaluator: &mut Evaluator, input: &str| match evaluator.eval(input) {
+            Err(Error::EvalError(err)) => err.span(),
+            result => panic!("expected an evaluation error, got {:?}", result),
+        };
+
+        assert_eq!(span(&mut evaluator, "1 + 2 / 0"), Some(4..9));
+        assert_eq!(span(&mut evaluator, "2 sqrt(-1) + 1"), Some(2..10));
+        assert_eq!(span(&mut evaluator, "1 + (y - 1)"), Some(5..6));
+        assert_eq!(span(&mut evaluator, "let x = 3 km + 2 s"), Some(8..18));
+
+        // errors inside a user function are reported at the call
+        evaluator.eval("let f(x) = 1 / x").unwrap();
+        assert_eq!(span(&mut evaluator, "2 * f(0)"), Some(4..8));
+    }
+
+    #[test]
+    fn test_suggestions() {
+        let mut evaluator = Evaluator::default();
+        evaluator.eval("let speed = 3").unwrap();
+        let help = |evaluator: &mut Evaluator, input: &str| match evaluator.eval(input) {
+            Err(Error::EvalError(err)) => err.help().map(str::to_string),
+            result => panic!("expected an evaluation error, got {:?}", result),
+        };
+
+        assert_eq!(
+            help(&mut evaluator, "sqr(2)").as_deref(),
+            Some("did you mean `sqrt`?")
+        );
+        assert_eq!(
+            help(&mut evaluator, "2 tua").as_deref(),
+            Some("did you mean `tau`?")
+        );
+        assert_eq!(
+            help(&mut evaluator, "sped / 2").as_deref(),
+            Some("did you mean `speed`?")
+        );
+        assert_eq!(
+            help(&mut evaluator, "2 sqrt x").as_deref(),
+            Some("`sqrt` is a function, call it as `sqrt(...)`")
+        );
+        assert_eq!(
+            help(&mut evaluator, "cso").as_deref(),
+            Some("`cos` is a function, did you mean `cos(...)`?")
+        );
+        assert_eq!(
+            help(&mut evaluator, "speed(2)").as_deref(),
+            Some("`speed` is a variable, not a function")
+        );
+        assert_eq!(help(&mut evaluator, "qwerty"), None);
+        assert_eq!(help(&mut evaluator, "q"), None);
+    }
+
+    #[test]
+    fn test_preview() {
+        let mut evaluator = Evaluator::default();
+        evaluator.eval("let x = 2").unwrap();
+        evaluator.eval("x + 1").unwrap();
+
+        assert_eq!(evaluator.preview("let x = 5").unwrap(), Value::from(5));
+        assert_eq!(evaluator.preview("_ * 10").unwrap(), Value::from(30));
+        assert!(evaluator.preview("let f(y) = y").unwrap().is_null());
+        assert_eq!(evaluator.eval("x").unwrap(), Value::from(2));
+        assert_eq!(evaluator.eval("_").unwrap(), Value::from(2));
+        assert!(evaluator.function("f").is_none());
+
+        evaluator
+            .eval("let g(n) = if n == 0 then 1 else g(n - 1) + g(n - 1)")
+            .unwrap();
+        assert!(matches!(
+            evaluator.preview("g(16)"),
+            Err(Error::EvalError(err)) if matches!(err.root(), EvalError::StepLimit)
+        ));
+        assert_eq!(evaluator.eval("g(4)").unwrap(), Value::from(16));
+    }
+
+    #[test]
+    fn test_register() {
+        let mut evaluator = Evaluator::default();
+        let calls = Rc::new(Cell::new(0));
+        let counter = calls.clone();
+        evaluator.register_function("tick", 0, move |_| {
+            counter.set(counter.get() + 1);
+            Ok(counter.get().into())
+        });
+        evaluator.define_constant("answer", 42);
+
+        assert_eq!(evaluator.eval("tick() + tick()").unwrap(), Value::from(3));
+        assert_eq!(calls.get(), 2);
+        assert_eq!(evaluator.eval("answer").unwrap(), Value::from(42));
+        assert!(!evaluator.set_variable("answer", 0));
+        assert!(evaluator.set_variable("y", 1.5));
+        assert_eq!(evaluator.eval("y").unwrap(), Value::from(1.5));
+    }
+
+    #[test]
+    fn test_inspect_definitions() {
+        let mut evaluator = Evaluator::default();
+        evaluator.eval("let f(a, b) = a  +  b # sum").unwrap();
+
+        let f = evaluator.function("f").unwrap();
+        assert_eq!(f.arity(), 2);
+        assert_eq!(
+            f.arg_names(),
+            Some(&[String::from("a"), String::from("b")][..])
+        );
+        assert!(f.body().is_some());
+        assert_eq!(f.source(), Some("a  +  b"));
+        assert_eq!(f.docstring(), Some("sum"));
+        assert_eq!(
+            evaluator.function("sin").unwrap().doc().unwrap().signature,
+            "sin(x)"
+        );
+        assert!(evaluator.function("sin").unwrap().arg_names().is_none());
+        assert!(evaluator.functions().any(|(name, _)| name == "f"));
+        assert!(evaluator.variables().any(|(name, _)| name == "pi"));
+    }
+
+    #[test]
+    fn test_to_script() {
+        let mut evaluator = Evaluator::default();
+        for input in [
+            "let n = 2^70",
+            "let r = -1/3",
+            "let x = 0.1",
+            "let big = 1e300",
+            "let inf = 1e999",
+            "let z = 1 - 2.5i",
+            "let yes = true",
+            "let speed = 3 km/h",
+            "let a = 30deg",
+            "let sq(x) = x^2 # the square",
+            "let hyp(a, b) = sqrt(sq(a) + sq(b))",
+        ] {
+            evaluator.eval(input).unwrap();
+        }
+
+        let script = evaluator.to_script();
+        assert!(script.contains("let sq(x) = x^2 # the square\n"));
+
+        let mut restored = Evaluator::default();
+        for line in script.lines() {
+            restored.eval(line).unwrap();
+        }
+        for name in ["n", "r", "x", "big", "inf", "z", "yes", "speed", "a"] {
+            assert_eq!(
+                restored.variable(name),
+                evaluator.variable(name),
+                "{}",
+                name
+            );
+        }
+        assert_eq!(restored.eval("hyp(3, 4)").unwrap(), Value::from(5.));
+        assert_eq!(restored.to_script(), script);
+    }
+
+    #[test]
+    fn test_to_script_since() {
+        let mut evaluator = Evaluator::default();
+        evaluator.eval("let g0 = 9.81").unwrap();
+        evaluator.eval("let c = 3").unwrap();
+        evaluator.eval("let sq(x) = x^2").unwrap();
+        let base = evaluator.clone();
+
+        evaluator.eval("let c = 4").unwrap();
+        evaluator.eval("let h = 2").unwrap();
+        assert_eq!(evaluator.to_script_since(&base), "let c = 4\nlet h = 2\n");
+        assert_eq!(base.to_script_since(&base), "");
+    }
+}
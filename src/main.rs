@@ -1,56 +1,169 @@
 mod args;
-mod default_context;
+mod commands;
+mod config;
 mod error_report;
-mod models;
-mod parser;
+mod json;
 mod readline;
+mod session;
+mod theme;
 
-use args::{Args, args};
-use chumsky::{
-    input::{Input, Stream},
-    prelude::*,
-};
-use default_context::create_context;
+use args::{Args, MAX_DEPTH, args};
+use config::{Config, config_dir};
 use directories::ProjectDirs;
-use error_report::ErrorReporter;
-use models::{Stmt, Token};
-use parser::parser;
+use error_report::{ErrorReporter, Location};
+use evar::{Evaluator, models::AngleUnit};
 use readline::SevaEditor;
 use rustyline::error::ReadlineError;
+use session::{Outcome, Session};
+use std::{
+    io::{ErrorKind, IsTerminal},
+    path::{Path, PathBuf},
+    process::ExitCode,
+};
+use theme::Theme;
 
-fn lex_and_parse(input: &str) -> Result<Stmt, Vec<Rich<'_, Token<'_>>>> {
-    let token_iter = models::token::lex(input).filter(|token| !matches!(token, (Token::Space, _)));
+/// Smallest stack size of the interpreter thread, also enough for deeply
+/// nested expressions.
+const STACK_SIZE: usize = 512 * 1024 * 1024;
 
-    let token_stream = Stream::from_iter(token_iter)
-        .map((input.len()..input.len()).into(), |(token, span)| {
-            (token, span.into())
-        });
+/// Stack reserved for each nested user function call. Calls take a few KiB
+/// in release builds and up to 200 KiB in debug builds, growing with how
+/// deeply the function body nests.
+const STACK_PER_CALL: usize = if cfg!(debug_assertions) {
+    512 * 1024
+} else {
+    32 * 1024
+};
+
+fn main() -> ExitCode {
+    let args = args().run();
 
-    parser().parse(token_stream).into_result()
+    // large enough for recursive user functions to hit the call depth limit
+    // before overflowing the stack
+    let stack_size = STACK_SIZE.max(args.max_depth.min(MAX_DEPTH) * STACK_PER_CALL);
+    std::thread::Builder::new()
+        .stack_size(stack_size)
+        .spawn(move || run(args))
+        .expect("failed to spawn interpreter thread")
+        .join()
+        .expect("interpreter thread panicked")
 }
 
-fn main() {
+fn run(args: Args) -> ExitCode {
     let Args {
         fix,
+        format,
         debug,
         no_color,
         angle_unit,
-    } = args().run();
+        complex,
+        max_depth,
+        keep_going,
+        json,
+        workspace,
+        exprs,
+    } = args;
 
-    let mut context = create_context(&angle_unit);
-    let mut editor = SevaEditor::new(no_color);
-    let mut reporter = ErrorReporter::new(no_color);
+    let config = Config::load();
+    let fix = fix.or(config.fix);
+    let format = format.or(config.format).unwrap_or_default();
+    let no_color = no_color || config.no_color;
+    let angle_unit = angle_unit
+        .or(config.angle_unit)
+        .unwrap_or(AngleUnit::Radian);
+    let prompt = config.prompt.unwrap_or_else(|| String::from("> "));
+    let truecolor = theme::truecolor_support();
+    let theme =
+        Theme::new(config.theme.as_deref(), &config.colors, truecolor).unwrap_or_else(|e| {
+            eprintln!("{}", e);
+            Theme::new(None, &config.colors, truecolor).expect("the default theme exists")
+        });
 
-    let seva_dirs =
-        ProjectDirs::from("", "enklht", "seva").expect("no valid home directory path retrieved");
-    let mut history_path = std::path::PathBuf::from(seva_dirs.data_local_dir());
+    let mut evaluator = Evaluator::new(&angle_unit, complex);
+    evaluator.context_mut().set_max_depth(max_depth);
+    evaluator.context_mut().set_fix(fix);
+    evaluator.context_mut().set_display_mode(format);
+    let reporter = ErrorReporter::new(no_color, &theme);
+    let mut session = Session::new(evaluator, reporter, debug, json);
+
+    match session.run_silently(&config_dir().join("init.evar")) {
+        Ok(_) => {}
+        Err(e) if e.kind() == ErrorKind::NotFound => {}
+        Err(e) => eprintln!("failed to run init.evar: {}", e),
+    }
+    session.snapshot();
+
+    let workspace = workspace.map(|name| data_dir().join("workspaces").join(name + ".evar"));
+    if let Some(path) = &workspace {
+        match session.run_silently(path) {
+            Ok(_) => {}
+            Err(e) if e.kind() == ErrorKind::NotFound => {}
+            Err(e) => eprintln!("failed to load workspace {}: {}", path.display(), e),
+        }
+    }
+
+    let exit_code = if !exprs.is_empty() {
+        run_batch(&mut session, &exprs, keep_going)
+    } else if !std::io::stdin().is_terminal() {
+        let success = session.run_script("<stdin>", std::io::stdin().lock(), keep_going);
+        exit_code(success)
+    } else {
+        run_repl(&mut session, no_color, prompt, theme)
+    };
+
+    if let Some(path) = &workspace {
+        let saved = path
+            .parent()
+            .map_or(Ok(()), std::fs::create_dir_all)
+            .and_then(|_| session.save(path));
+        if let Err(e) = saved {
+            eprintln!("failed to save workspace {}: {}", path.display(), e);
+        }
+    }
+
+    exit_code
+}
 
-    match std::fs::create_dir_all(&history_path) {
+/// Evaluates each expression or script file given on the command line. An
+/// argument is only run as a script if it is not valid input, so `x` is
+/// evaluated even when a file named `x` exists.
+fn run_batch(session: &mut Session, exprs: &[String], keep_going: bool) -> ExitCode {
+    let mut success = true;
+    for (index, input) in exprs.iter().enumerate() {
+        let outcome = if !session.parses(input) && Path::new(input).is_file() {
+            run_script_file(session, input, keep_going).into()
+        } else {
+            let name = format!("<arg {}>", index + 1);
+            let location = Location {
+                name: &name,
+                line: 1,
+            };
+            session.execute(input, location, None)
+        };
+        match outcome {
+            Outcome::Success => {}
+            Outcome::Failure => {
+                success = false;
+                if !keep_going {
+                    break;
+                }
+            }
+            Outcome::Quit => break,
+        }
+    }
+    exit_code(success)
+}
+
+fn run_repl(session: &mut Session, no_color: bool, prompt: String, theme: Theme) -> ExitCode {
+    let mut editor = SevaEditor::new(no_color, prompt, theme, session.evaluator.clone());
+
+    let data_dir = data_dir();
+    match std::fs::create_dir_all(&data_dir) {
         Ok(_) => {}
         Err(e) => eprintln!("failed to create data directory: {}", e),
     };
 
-    history_path.push("history.txt");
+    let history_path = data_dir.join("history.txt");
 
     match editor.load_history(history_path.as_path()) {
         Ok(_) => {}
@@ -60,26 +173,8 @@ fn main() {
     loop {
         match editor.readline() {
             Ok(input) => {
-                if input == "exit" || input == "quit" {
+                if session.execute(&input, Location::repl(), Some(&editor)) == Outcome::Quit {
                     break;
-                };
-
-                if input == "help" {
-                    context.print_help();
-                    continue;
-                }
-
-                match lex_and_parse(&input) {
-                    Ok(stmt) => {
-                        if debug {
-                            println!("{}", stmt)
-                        };
-                        match stmt.eval(&mut context) {
-                            Ok(out) => out.print(fix),
-                            Err(err) => eprintln!("{}", err),
-                        }
-                    }
-                    Err(errs) => reporter.report_error(errs, &input),
                 }
             }
             Err(ReadlineError::Interrupted) | Err(ReadlineError::Eof) => {
@@ -96,4 +191,30 @@ fn main() {
         Ok(_) => {}
         Err(e) => eprintln!("failed to save history: {}", e),
     }
+
+    ExitCode::SUCCESS
+}
+
+/// Directory for the history and workspaces.
+fn data_dir() -> PathBuf {
+    let seva_dirs =
+        ProjectDirs::from("", "enklht", "seva").expect("no valid home directory path retrieved");
+    PathBuf::from(seva_dirs.data_local_dir())
+}
+
+fn run_script_file(session: &mut Session, path: &str, keep_going: bool) -> bool {
+    session
+        .run_script_file(path, keep_going)
+        .unwrap_or_else(|e| {
+            eprintln!("failed to open {}: {}", path, e);
+            false
+        })
+}
+
+fn exit_code(success: bool) -> ExitCode {
+    if success {
+        ExitCode::SUCCESS
+    } else {
+        ExitCode::FAILURE
+    }
 }
@@ -1,54 +1,287 @@
-use super::{Function, Value, Variable};
+use super::{BaseUnit, DisplayMode, EvalError, Function, Unit, Value, Variable};
 use crate::models::Expr;
 use std::collections::HashMap;
+use std::rc::Rc;
 
+pub const DEFAULT_MAX_DEPTH: usize = 1000;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AngleUnit {
+    Radian,
+    Degrees,
+    Gradians,
+}
+
+impl AngleUnit {
+    /// Size of one unit in radians.
+    pub fn radians(&self) -> f64 {
+        use std::f64::consts::PI;
+        match self {
+            AngleUnit::Radian => 1.,
+            AngleUnit::Degrees => PI / 180.,
+            AngleUnit::Gradians => PI / 200.,
+        }
+    }
+
+    /// Converts an angle in radians to this unit.
+    pub fn from_radians(&self, x: f64) -> f64 {
+        use std::f64::consts::PI;
+        match self {
+            AngleUnit::Radian => x,
+            AngleUnit::Degrees => x.to_degrees(),
+            AngleUnit::Gradians => x * 200. / PI,
+        }
+    }
+}
+
+impl std::fmt::Display for AngleUnit {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AngleUnit::Radian => write!(f, "rad"),
+            AngleUnit::Degrees => write!(f, "deg"),
+            AngleUnit::Gradians => write!(f, "grad"),
+        }
+    }
+}
+
+impl std::str::FromStr for AngleUnit {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "rad" | "radian" | "radians" => Ok(AngleUnit::Radian),
+            "deg" | "degree" | "degrees" => Ok(AngleUnit::Degrees),
+            "grad" | "gradian" | "gradians" => Ok(AngleUnit::Gradians),
+            _ => Err(format!(
+                "unknown angle unit: {} (expected rad, deg or grad)",
+                s
+            )),
+        }
+    }
+}
+
+#[derive(Clone)]
 pub struct Context {
     previous_answer: Option<Value>,
     functions: HashMap<String, Function>,
-    variables: Option<Box<VariableContext>>,
+    variables: VariableContext,
+    frames: Vec<VariableContext>,
+    max_depth: usize,
+    complex: bool,
+    /// Expressions left to evaluate before giving up, if limited.
+    steps: Option<usize>,
+    angle_unit: AngleUnit,
+    fix: Option<usize>,
+    display_mode: DisplayMode,
+    units: HashMap<String, Rc<BaseUnit>>,
 }
 
 impl Context {
     pub fn new(
         functions: HashMap<String, Function>,
         variables: HashMap<String, Variable>,
+        units: HashMap<String, Rc<BaseUnit>>,
     ) -> Context {
         Context {
             previous_answer: None,
             functions,
-            variables: Some(Box::new(VariableContext::new(variables))),
+            variables: VariableContext::new(variables),
+            frames: Vec::new(),
+            max_depth: DEFAULT_MAX_DEPTH,
+            complex: false,
+            steps: None,
+            angle_unit: AngleUnit::Radian,
+            fix: None,
+            display_mode: DisplayMode::Decimal,
+            units,
         }
     }
 
-    pub fn extend(&mut self) {
-        let variables = self.variables.take();
-        self.variables = Some(Box::new(VariableContext {
-            parent: variables,
-            variables: HashMap::new(),
-        }))
+    pub fn set_max_depth(&mut self, max_depth: usize) {
+        self.max_depth = max_depth;
     }
 
-    pub fn get_variable(&self, name: &str) -> Option<Variable> {
-        match &self.variables {
-            Some(variables) => variables.get_variable(name),
-            None => unreachable!(),
+    /// Whether operations leaving the reals, such as `(-1)^(1/2)`, give
+    /// complex results.
+    pub fn complex(&self) -> bool {
+        self.complex
+    }
+
+    pub fn set_complex(&mut self, complex: bool) {
+        self.complex = complex;
+    }
+
+    /// Limits how many more expressions may be evaluated, so that previews
+    /// of expensive input give up instead of blocking.
+    pub fn set_step_limit(&mut self, steps: Option<usize>) {
+        self.steps = steps;
+    }
+
+    /// Counts one evaluated expression against the step limit.
+    pub(crate) fn step(&mut self) -> Result<(), EvalError> {
+        match &mut self.steps {
+            Some(0) => Err(EvalError::StepLimit),
+            Some(steps) => {
+                *steps -= 1;
+                Ok(())
+            }
+            None => Ok(()),
+        }
+    }
+
+    /// The unit trigonometric functions take and return angles in.
+    pub fn angle_unit(&self) -> AngleUnit {
+        self.angle_unit
+    }
+
+    pub fn set_angle_unit(&mut self, angle_unit: AngleUnit) {
+        self.angle_unit = angle_unit;
+    }
+
+    /// Number of decimal places results are printed with.
+    pub fn fix(&self) -> Option<usize> {
+        self.fix
+    }
+
+    pub fn set_fix(&mut self, fix: Option<usize>) {
+        self.fix = fix;
+    }
+
+    /// How results are printed unless a statement asks for another mode.
+    pub fn display_mode(&self) -> DisplayMode {
+        self.display_mode
+    }
+
+    pub fn set_display_mode(&mut self, display_mode: DisplayMode) {
+        self.display_mode = display_mode;
+    }
+
+    /// Runs `f` inside a new call frame holding `variables`. The frame is
+    /// popped again when `f` returns, whether it succeeded or not.
+    pub fn with_frame<T>(
+        &mut self,
+        variables: HashMap<String, Variable>,
+        f: impl FnOnce(&mut Context) -> Result<T, EvalError>,
+    ) -> Result<T, EvalError> {
+        if self.frames.len() >= self.max_depth {
+            return Err(EvalError::StackOverflow(self.max_depth));
         }
+        self.frames.push(VariableContext::new(variables));
+        let result = f(self);
+        self.frames.pop();
+        result
+    }
+
+    pub fn get_variable(&self, name: &str) -> Option<Variable> {
+        self.frames
+            .last()
+            .and_then(|frame| frame.get_variable(name))
+            .or_else(|| self.variables.get_variable(name))
     }
 
     pub fn set_variable(&mut self, name: &str, value: Value) -> Option<Value> {
-        match &mut self.variables {
-            Some(variables) => variables.set_variable(name, value),
-            None => unreachable!(),
+        match self.frames.last_mut() {
+            Some(frame) => frame.set_variable(name, value),
+            None => self.variables.set_variable(name, value),
         }
     }
 
+    /// Removes a global user-defined variable. Returns whether it existed.
+    pub fn remove_variable(&mut self, name: &str) -> bool {
+        self.variables.remove_variable(name)
+    }
+
     pub fn get_function(&self, name: &str) -> Option<&Function> {
         self.functions.get(name)
     }
 
-    pub fn set_function(&mut self, name: &str, arg_names: Vec<String>, body: Expr) {
-        self.functions
-            .insert(name.to_string(), Function::new_internal(arg_names, body));
+    pub fn set_function(
+        &mut self,
+        name: &str,
+        arg_names: Vec<String>,
+        body: Expr,
+        source: String,
+        doc: Option<String>,
+    ) {
+        self.functions.insert(
+            name.to_string(),
+            Function::new_internal(arg_names, body, source, doc),
+        );
+    }
+
+    /// Removes a user-defined function. Returns whether it existed.
+    pub fn remove_function(&mut self, name: &str) -> bool {
+        match self.functions.get(name) {
+            Some(function) if !function.is_external() => {
+                self.functions.remove(name);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Adds a builtin function, replacing any function of the same name.
+    pub fn define_function(&mut self, name: &str, function: Function) {
+        self.functions.insert(name.to_string(), function);
+    }
+
+    /// Adds a constant that cannot be redefined with `let`.
+    pub fn define_constant(&mut self, name: &str, value: Value) {
+        self.variables
+            .variables
+            .insert(name.to_string(), Variable::External(value));
+    }
+
+    pub fn functions(&self) -> impl Iterator<Item = (&String, &Function)> {
+        self.functions.iter()
+    }
+
+    /// Global variables and constants.
+    pub fn variables(&self) -> impl Iterator<Item = (&String, &Variable)> {
+        self.variables.variables.iter()
+    }
+
+    /// Error for a call to an unknown function, suggesting the closest
+    /// function name.
+    pub fn function_not_found(&self, name: &str) -> EvalError {
+        let help = if self.get_variable(name).is_some() {
+            Some(format!("`{}` is a variable, not a function", name))
+        } else {
+            closest(name, self.functions.keys()).map(|f| format!("did you mean `{}`?", f))
+        };
+        EvalError::FunctionNotFound(name.to_string(), help)
+    }
+
+    /// Error for an unknown variable, suggesting the closest variable or
+    /// unit name, or the function or unit the name refers to.
+    pub fn variable_not_found(&self, name: &str) -> EvalError {
+        let variables = self
+            .frames
+            .last()
+            .into_iter()
+            .flat_map(|frame| frame.variables.keys())
+            .chain(self.variables.variables.keys())
+            .chain(self.units.keys());
+        let help = if self.functions.contains_key(name) {
+            Some(format!("`{0}` is a function, call it as `{0}(...)`", name))
+        } else if self.units.contains_key(name) {
+            Some(format!(
+                "`{0}` is a unit, write it after a number as in `1 {0}`",
+                name
+            ))
+        } else {
+            match closest(name, variables.chain(self.functions.keys())) {
+                Some(f) if self.functions.contains_key(f) => {
+                    Some(format!("`{0}` is a function, did you mean `{0}(...)`?", f))
+                }
+                suggestion => suggestion.map(|v| format!("did you mean `{}`?", v)),
+            }
+        };
+        EvalError::VariableNotFound(name.to_string(), help)
+    }
+
+    pub fn get_unit(&self, name: &str) -> Option<Unit> {
+        self.units.get(name).cloned().map(Unit::new)
     }
 
     pub fn get_prev_answer(&self) -> Option<Value> {
@@ -60,26 +293,64 @@ impl Context {
     }
 }
 
+/// The candidate closest to `name` by edit distance, if it is close enough
+/// to be a likely typo.
+fn closest<'a>(name: &str, candidates: impl Iterator<Item = &'a String>) -> Option<&'a str> {
+    let max_distance = name.chars().count().max(3) / 3;
+    candidates
+        .map(|candidate| (edit_distance(name, candidate), candidate.as_str()))
+        .filter(|&(distance, _)| distance <= max_distance && distance < name.chars().count())
+        .min()
+        .map(|(_, candidate)| candidate)
+}
+
+/// Number of insertions, deletions, substitutions and transpositions of
+/// adjacent characters needed to turn `a` into `b`.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let a = a.chars().collect::<Vec<_>>();
+    let b = b.chars().collect::<Vec<_>>();
+    // distances[i][j] is the distance between a[..i] and b[..j]
+    let mut distances = vec![vec![0; b.len() + 1]; a.len() + 1];
+    for (i, row) in distances.iter_mut().enumerate() {
+        row[0] = i;
+    }
+    distances[0] = (0..=b.len()).collect();
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let cost = usize::from(a[i - 1] != b[j - 1]);
+            let mut distance = (distances[i - 1][j] + 1)
+                .min(distances[i][j - 1] + 1)
+                .min(distances[i - 1][j - 1] + cost);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                distance = distance.min(distances[i - 2][j - 2] + 1);
+            }
+            distances[i][j] = distance;
+        }
+    }
+    distances[a.len()][b.len()]
+}
+
+#[derive(Clone)]
 struct VariableContext {
-    parent: Option<Box<VariableContext>>,
     variables: HashMap<String, Variable>,
 }
 
 impl VariableContext {
     fn new(variables: HashMap<String, Variable>) -> VariableContext {
-        VariableContext {
-            parent: None,
-            variables,
-        }
+        VariableContext { variables }
     }
 
     fn get_variable(&self, name: &str) -> Option<Variable> {
-        if let Some(val) = self.variables.get(name) {
-            Some(val.clone())
-        } else if let Some(parent) = &self.parent {
-            parent.get_variable(name)
-        } else {
-            None
+        self.variables.get(name).cloned()
+    }
+
+    fn remove_variable(&mut self, name: &str) -> bool {
+        match self.variables.get(name) {
+            Some(Variable::Internal(_)) => {
+                self.variables.remove(name);
+                true
+            }
+            _ => false,
         }
     }
 
@@ -104,78 +375,47 @@ impl VariableContext {
 
 impl Context {
     pub fn print_help(&self) {
-        println!("intrinsic functions:");
-        let mut entries = self
-            .functions
-            .iter()
-            .filter(|e| e.1.is_external())
-            .collect::<Vec<_>>();
-        entries.sort_by_key(|e| e.0);
-
-        for (i, key) in entries.iter().map(|e| e.0).enumerate() {
-            print!("{}\t", key);
-            if i % 8 == 7 {
-                println!()
-            }
-        }
-        println!("\n");
-
-        self.variables.as_ref().unwrap().print_constants();
-
-        println!("user defined functions:");
-        let mut entries = self
-            .functions
-            .iter()
-            .filter(|e| !e.1.is_external())
-            .collect::<Vec<_>>();
-        entries.sort_by_key(|e| e.0);
-
-        for (i, key) in entries.iter().map(|e| e.0).enumerate() {
-            print!("{}\t", key);
-            if i % 8 == 7 {
-                println!()
-            }
-        }
-        println!("\n");
+        print!("{}", self.help());
+    }
+
+    /// The builtin and user-defined names, as listed by `:help`.
+    pub fn help(&self) -> String {
+        let functions = |external: bool| {
+            self.functions
+                .iter()
+                .filter(move |e| e.1.is_external() == external)
+                .map(|e| e.0)
+        };
+        let variables = |external: bool| {
+            self.variables
+                .variables
+                .iter()
+                .filter(move |e| e.1.is_external() == external)
+                .map(|e| e.0)
+        };
 
-        self.variables.as_ref().unwrap().print_variables();
+        name_table("intrinsic functions", functions(true))
+            + "\n\n"
+            + &name_table("constants", variables(true))
+            + "\n\n"
+            + &name_table("user defined functions", functions(false))
+            + "\n\n"
+            + &name_table("variables", variables(false))
+            + "\n"
     }
 }
 
-impl VariableContext {
-    fn print_constants(&self) {
-        println!("constants:");
-        let mut entries = self
-            .variables
-            .iter()
-            .filter(|e| e.1.is_external())
-            .collect::<Vec<_>>();
-        entries.sort_by_key(|e| e.0);
-
-        for (i, key) in entries.iter().map(|e| e.0).enumerate() {
-            print!("{}\t", key);
-            if i % 8 == 7 {
-                println!()
-            }
-        }
-        println!("\n");
-    }
+/// Lists names under a heading, eight to a line.
+fn name_table<'a>(heading: &str, names: impl Iterator<Item = &'a String>) -> String {
+    let mut names = names.collect::<Vec<_>>();
+    names.sort();
 
-    fn print_variables(&self) {
-        println!("variables:");
-        let mut entries = self
-            .variables
-            .iter()
-            .filter(|e| !e.1.is_external())
-            .collect::<Vec<_>>();
-        entries.sort_by_key(|e| e.0);
-
-        for (i, key) in entries.iter().map(|e| e.0).enumerate() {
-            print!("{}\t", key);
-            if i % 8 == 7 {
-                println!()
-            }
+    let mut table = format!("{}:\n", heading);
+    for (i, name) in names.iter().enumerate() {
+        table += &format!("{}\t", name);
+        if i % 8 == 7 {
+            table.push('\n');
         }
-        println!();
     }
+    table
 }
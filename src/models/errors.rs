@@ -1,4 +1,10 @@
+use super::Value;
+use chumsky::{
+    error::{Rich, RichPattern, RichReason},
+    span::SimpleSpan,
+};
 use rustyline::error::ReadlineError;
+use std::ops::Range;
 use thiserror::Error;
 
 #[derive(Debug, Error)]
@@ -15,23 +21,280 @@ pub enum EvalError {
     #[error("convertion from {0} to {1} is not supported")]
     InvalidConversion(String, String),
 
+    #[error("incompatible units: {0} and {1}")]
+    IncompatibleUnits(String, String),
+
     #[error("type error (expected: {0}, found: {1})")]
     TypeError(String, String),
 
     #[error("invalid number of arguments (expected: {0}, found: {1})")]
     InvalidNumberOfArguments(usize, usize),
 
+    /// The name, and help such as a suggested name.
     #[error("function not found: {0}")]
-    FunctionNotFound(String),
+    FunctionNotFound(String, Option<String>),
 
     #[error("variable not found: {0}")]
-    VariableNotFound(String),
+    VariableNotFound(String, Option<String>),
 
     #[error("failed to define a variable: {0}")]
     InvalidVariableDefinition(String),
 
     #[error("no previous answer")]
     NoHistory,
+
+    #[error("stack overflow (maximum call depth is {0})")]
+    StackOverflow(usize),
+
+    #[error("evaluation step limit reached")]
+    StepLimit,
+
+    /// An error raised while evaluating the sub-expression at `span`.
+    #[error("{error}")]
+    Spanned {
+        error: Box<EvalError>,
+        span: SimpleSpan,
+    },
+
+    /// An error that unwound through calls to user-defined functions,
+    /// innermost call first.
+    #[error("{error}")]
+    Traced {
+        error: Box<EvalError>,
+        trace: Vec<Frame>,
+    },
+}
+
+impl EvalError {
+    /// Name of the error variant, used in machine-readable output.
+    pub fn kind(&self) -> &'static str {
+        use EvalError::*;
+        match self {
+            DivisionByZero => "DivisionByZero",
+            MathDomain(_) => "MathDomain",
+            Overflow => "Overflow",
+            InvalidConversion(..) => "InvalidConversion",
+            IncompatibleUnits(..) => "IncompatibleUnits",
+            TypeError(..) => "TypeError",
+            InvalidNumberOfArguments(..) => "InvalidNumberOfArguments",
+            FunctionNotFound(..) => "FunctionNotFound",
+            VariableNotFound(..) => "VariableNotFound",
+            InvalidVariableDefinition(_) => "InvalidVariableDefinition",
+            NoHistory => "NoHistory",
+            StackOverflow(_) => "StackOverflow",
+            StepLimit => "StepLimit",
+            Spanned { error, .. } | Traced { error, .. } => error.kind(),
+        }
+    }
+
+    /// Attaches the span of the sub-expression the error was raised in,
+    /// unless a narrower one is already attached.
+    pub fn at(self, span: SimpleSpan) -> EvalError {
+        match self {
+            EvalError::Spanned { .. } => self,
+            error => EvalError::Spanned {
+                error: Box::new(error),
+                span,
+            },
+        }
+    }
+
+    /// Span of the failing sub-expression in the input, if known.
+    pub fn span(&self) -> Option<Range<usize>> {
+        match self {
+            EvalError::Spanned { span, .. } => Some(span.into_range()),
+            _ => None,
+        }
+    }
+
+    /// A hint on how to fix the error.
+    pub fn help(&self) -> Option<&str> {
+        match self.root() {
+            EvalError::FunctionNotFound(_, help) | EvalError::VariableNotFound(_, help) => {
+                help.as_deref()
+            }
+            _ => None,
+        }
+    }
+
+    /// Calls to user-defined functions the error unwound through, innermost
+    /// first.
+    pub fn trace(&self) -> &[Frame] {
+        match self {
+            EvalError::Spanned { error, .. } => error.trace(),
+            EvalError::Traced { trace, .. } => trace,
+            _ => &[],
+        }
+    }
+
+    /// Records a call the error unwound through.
+    pub fn in_call(self, frame: Frame) -> EvalError {
+        match self {
+            EvalError::Traced { error, mut trace } => {
+                trace.push(frame);
+                EvalError::Traced { error, trace }
+            }
+            error => EvalError::Traced {
+                error: Box::new(error),
+                trace: vec![frame],
+            },
+        }
+    }
+
+    /// The error without its span and trace.
+    pub fn root(&self) -> &EvalError {
+        match self {
+            EvalError::Spanned { error, .. } | EvalError::Traced { error, .. } => error.root(),
+            error => error,
+        }
+    }
+
+    /// Drops the span, for errors whose span refers to other source than the
+    /// input being evaluated.
+    pub fn without_span(self) -> EvalError {
+        match self {
+            EvalError::Spanned { error, .. } => *error,
+            error => error,
+        }
+    }
+}
+
+/// A call to a user-defined function that an error unwound through.
+#[derive(Debug, Clone)]
+pub struct Frame {
+    pub function: String,
+    pub args: Vec<(String, Value)>,
+    /// The sub-expression of the body that failed, as written.
+    pub expr: Option<String>,
+}
+
+impl std::fmt::Display for Frame {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let args = self
+            .args
+            .iter()
+            .map(|(name, value)| format!("{} = {}", name, value))
+            .collect::<Vec<_>>();
+        write!(f, "in {}({})", self.function, args.join(", "))?;
+        match &self.expr {
+            Some(expr) => write!(f, " at `{}`", expr),
+            None => Ok(()),
+        }
+    }
+}
+
+/// A syntax error, detached from the input it was found in.
+#[derive(Debug, Clone, PartialEq, Error)]
+#[error("{message}")]
+pub struct ParseError {
+    pub message: String,
+    pub span: Range<usize>,
+    /// The constructs being parsed when the error occurred, innermost first.
+    pub contexts: Vec<(String, Range<usize>)>,
+}
+
+impl<T: std::fmt::Display> From<Rich<'_, T>> for ParseError {
+    fn from(err: Rich<'_, T>) -> Self {
+        let message = match err.reason() {
+            RichReason::ExpectedFound { expected, found } => {
+                let found = match found {
+                    Some(token) => format!("`{}`", **token),
+                    None => String::from("end of input"),
+                };
+                format!("expected {}, found {}", describe_expected(expected), found)
+            }
+            RichReason::Custom(message) => message.clone(),
+        };
+        ParseError {
+            message,
+            span: err.span().into_range(),
+            contexts: err
+                .contexts()
+                .map(|(label, span)| (label.to_string(), span.into_range()))
+                .collect(),
+        }
+    }
+}
+
+const OPERATORS: [&str; 22] = [
+    "+", "*", "/", "//", "%", "^", "!", "==", "!=", "<", "<=", ">", ">=", "to", "and", "or", "&",
+    "|", "xor", "<<", ">>", "as",
+];
+
+/// Tokens that can start an expression, covered by "an expression".
+const OPERANDS: [&str; 6] = ["(", "_", "-", "~", "not", "if"];
+
+/// Summarises what the parser expected in words, e.g. "an operator or `)`",
+/// rather than listing every token.
+fn describe_expected<T: std::fmt::Display>(expected: &[RichPattern<'_, T>]) -> String {
+    let mut name = false;
+    let mut operand = false;
+    let mut operator = false;
+    let mut end = false;
+    let mut labels = Vec::new();
+    let mut tokens = Vec::new();
+    for pattern in expected {
+        match pattern {
+            RichPattern::Token(token) => tokens.push(token.to_string()),
+            RichPattern::Label(label) => match &**label {
+                "ident" => name = true,
+                "number" | "expression" => operand = true,
+                label => labels.push(label.to_string()),
+            },
+            RichPattern::Identifier(keyword) => tokens.push(keyword.clone()),
+            RichPattern::EndOfInput => end = true,
+            _ => {}
+        }
+    }
+    tokens.retain(|token| {
+        if OPERATORS.contains(&token.as_str()) {
+            operator = true;
+            false
+        } else {
+            !(operand && OPERANDS.contains(&token.as_str()))
+        }
+    });
+    if operator {
+        // after an operand, an operand would be implicit multiplication; an
+        // operator is the more likely fix
+        tokens.retain(|token| token != "-");
+        name &= !operand;
+        operand = false;
+    }
+    name &= !operand;
+
+    let mut items = Vec::new();
+    if name {
+        items.push(String::from("a name"));
+    }
+    if operand {
+        items.push(String::from("an expression"));
+    }
+    if operator {
+        items.push(String::from("an operator"));
+    }
+    items.extend(labels);
+    items.extend(tokens.iter().map(|token| format!("`{}`", token)));
+    if end {
+        items.push(String::from("end of input"));
+    }
+
+    match &items[..] {
+        [] => String::from("something else"),
+        [item] => item.clone(),
+        [first, second] => format!("{} or {}", first, second),
+        [init @ .., last] => format!("{}, or {}", init.join(", "), last),
+    }
+}
+
+/// Any error from evaluating a line of input.
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("syntax error: {}", .0.iter().map(ToString::to_string).collect::<Vec<_>>().join("; "))]
+    ParseError(Vec<ParseError>),
+
+    #[error(transparent)]
+    EvalError(#[from] EvalError),
 }
 
 #[derive(Debug, Error)]
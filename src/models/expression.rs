@@ -1,10 +1,18 @@
 use super::{Context, operators::*};
 use super::{EvalError, Value};
+use chumsky::span::SimpleSpan;
+use num_bigint::BigInt;
+use std::cmp::Ordering;
+use std::ops::Neg;
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug)]
 pub enum Expr {
-    Int(i32),
+    Int(BigInt),
     Float(f64),
+    Quantity {
+        magnitude: f64,
+        unit: String,
+    },
     Variable(String),
     FnCall {
         name: String,
@@ -23,7 +31,77 @@ pub enum Expr {
         lhs: Box<Expr>,
         rhs: Box<Expr>,
     },
+    If {
+        cond: Box<Expr>,
+        then_expr: Box<Expr>,
+        else_expr: Box<Expr>,
+    },
     PrevAnswer,
+    /// Placeholder for input that failed to parse, only produced while
+    /// recovering from syntax errors.
+    Error,
+    /// An expression with the span of the input it was parsed from.
+    Spanned {
+        expr: Box<Expr>,
+        span: SimpleSpan,
+    },
+}
+
+/// Expressions are equal if they have the same structure, wherever they
+/// were parsed from.
+impl PartialEq for Expr {
+    fn eq(&self, other: &Expr) -> bool {
+        match (self, other) {
+            (Expr::Spanned { expr, .. }, other) | (other, Expr::Spanned { expr, .. }) => {
+                **expr == *other
+            }
+            (Expr::Int(a), Expr::Int(b)) => a == b,
+            (Expr::Float(a), Expr::Float(b)) => a == b,
+            (
+                Expr::Quantity { magnitude, unit },
+                Expr::Quantity {
+                    magnitude: magnitude2,
+                    unit: unit2,
+                },
+            ) => magnitude == magnitude2 && unit == unit2,
+            (Expr::Variable(a), Expr::Variable(b)) => a == b,
+            (
+                Expr::FnCall { name, args },
+                Expr::FnCall {
+                    name: name2,
+                    args: args2,
+                },
+            ) => name == name2 && args == args2,
+            (Expr::PrefixOp { op, arg }, Expr::PrefixOp { op: op2, arg: arg2 }) => {
+                op == op2 && arg == arg2
+            }
+            (Expr::PostfixOp { op, arg }, Expr::PostfixOp { op: op2, arg: arg2 }) => {
+                op == op2 && arg == arg2
+            }
+            (
+                Expr::InfixOp { op, lhs, rhs },
+                Expr::InfixOp {
+                    op: op2,
+                    lhs: lhs2,
+                    rhs: rhs2,
+                },
+            ) => op == op2 && lhs == lhs2 && rhs == rhs2,
+            (
+                Expr::If {
+                    cond,
+                    then_expr,
+                    else_expr,
+                },
+                Expr::If {
+                    cond: cond2,
+                    then_expr: then_expr2,
+                    else_expr: else_expr2,
+                },
+            ) => cond == cond2 && then_expr == then_expr2 && else_expr == else_expr2,
+            (Expr::PrevAnswer, Expr::PrevAnswer) | (Expr::Error, Expr::Error) => true,
+            _ => false,
+        }
+    }
 }
 
 impl std::fmt::Display for Expr {
@@ -31,6 +109,7 @@ impl std::fmt::Display for Expr {
         match self {
             Expr::Int(n) => write!(f, "(int: {})", n),
             Expr::Float(n) => write!(f, "(float: {})", n),
+            Expr::Quantity { magnitude, unit } => write!(f, "(quantity: {} {})", magnitude, unit),
             Expr::Variable(n) => write!(f, "{}", n),
             Expr::FnCall { name, args } => {
                 let args_str = args
@@ -43,32 +122,91 @@ impl std::fmt::Display for Expr {
             Expr::PrefixOp { op, arg } => write!(f, "({}{})", op, arg),
             Expr::PostfixOp { op, arg } => write!(f, "({}{})", arg, op),
             Expr::InfixOp { op, lhs, rhs } => write!(f, "({} {} {})", lhs, op, rhs),
+            Expr::If {
+                cond,
+                then_expr,
+                else_expr,
+            } => write!(f, "(if {} then {} else {})", cond, then_expr, else_expr),
             Expr::PrevAnswer => write!(f, "_"),
+            Expr::Error => write!(f, "<error>"),
+            Expr::Spanned { expr, .. } => write!(f, "{}", expr),
         }
     }
 }
 
 impl Expr {
     pub fn eval(&self, context: &mut Context) -> Result<Value, EvalError> {
+        context.step()?;
         match self {
-            Expr::Int(f) => Ok(Value::from(*f)),
+            Expr::Int(n) => Ok(Value::from(n.clone())),
             Expr::Float(f) => Ok(Value::from(*f)),
+            Expr::Quantity { magnitude, unit } => context
+                .get_unit(unit)
+                .map(|unit| Value::quantity(*magnitude, unit))
+                .ok_or_else(|| context.variable_not_found(unit)),
+            Expr::InfixOp { .. } if self.is_quantity_literal() => self.eval_with_units(context),
             Expr::InfixOp { op, lhs, rhs } => {
                 use InfixOp::*;
                 match op {
-                    Add => Ok(lhs.eval(context)? + rhs.eval(context)?),
-                    Sub => Ok(lhs.eval(context)? - rhs.eval(context)?),
-                    Mul => Ok(lhs.eval(context)? * rhs.eval(context)?),
+                    Add => lhs.eval(context)? + rhs.eval(context)?,
+                    Sub => lhs.eval(context)? - rhs.eval(context)?,
+                    Mul => lhs.eval(context)? * rhs.eval(context)?,
                     Div => lhs.eval(context)? / rhs.eval(context)?,
                     IntDiv => lhs.eval(context)?.int_div(rhs.eval(context)?),
                     Rem => lhs.eval(context)?.rem_euclid(rhs.eval(context)?),
-                    Pow => Ok(lhs.eval(context)?.pow(rhs.eval(context)?)),
+                    Pow => lhs
+                        .eval(context)?
+                        .pow(rhs.eval(context)?, context.complex()),
+                    Convert => lhs.eval(context)?.convert(rhs.eval_with_units(context)?),
+                    BitAnd => lhs.eval(context)?.bit_and(rhs.eval(context)?),
+                    BitOr => lhs.eval(context)?.bit_or(rhs.eval(context)?),
+                    BitXor => lhs.eval(context)?.bit_xor(rhs.eval(context)?),
+                    Shl => lhs.eval(context)?.shift_left(rhs.eval(context)?),
+                    Shr => lhs.eval(context)?.shift_right(rhs.eval(context)?),
+                    Eq => Ok(lhs.eval(context)?.equals(&rhs.eval(context)?)?.into()),
+                    Ne => Ok((!lhs.eval(context)?.equals(&rhs.eval(context)?)?).into()),
+                    Lt => Ok(lhs
+                        .eval(context)?
+                        .compare(&rhs.eval(context)?)?
+                        .is_some_and(Ordering::is_lt)
+                        .into()),
+                    Le => Ok(lhs
+                        .eval(context)?
+                        .compare(&rhs.eval(context)?)?
+                        .is_some_and(Ordering::is_le)
+                        .into()),
+                    Gt => Ok(lhs
+                        .eval(context)?
+                        .compare(&rhs.eval(context)?)?
+                        .is_some_and(Ordering::is_gt)
+                        .into()),
+                    Ge => Ok(lhs
+                        .eval(context)?
+                        .compare(&rhs.eval(context)?)?
+                        .is_some_and(Ordering::is_ge)
+                        .into()),
+                    And => {
+                        if lhs.eval(context)?.to_bool()? {
+                            Ok(rhs.eval(context)?.to_bool()?.into())
+                        } else {
+                            Ok(false.into())
+                        }
+                    }
+                    Or => {
+                        if lhs.eval(context)?.to_bool()? {
+                            Ok(true.into())
+                        } else {
+                            Ok(rhs.eval(context)?.to_bool()?.into())
+                        }
+                    }
                 }
             }
             Expr::PrefixOp { op, arg } => {
                 use PrefixOp::*;
                 match op {
-                    Neg => Ok(-arg.eval(context)?),
+                    Neg => arg.eval(context)?.neg(),
+                    Not => Ok((!arg.eval(context)?.to_bool()?).into()),
+                    BitNot => arg.eval(context)?.bit_not(),
                 }
             }
             Expr::PostfixOp { op, arg } => {
@@ -85,19 +223,226 @@ impl Expr {
 
                 let function = context
                     .get_function(name)
-                    .ok_or(EvalError::FunctionNotFound(name.to_string()))?
+                    .ok_or_else(|| context.function_not_found(name))?
                     .clone();
 
-                function.call(evaluated_args, context)
+                function.call(name, evaluated_args, context)
             }
-            Expr::Variable(name) => {
-                let variable = context
-                    .get_variable(name)
-                    .ok_or(EvalError::VariableNotFound(name.to_string()))?
-                    .get();
-                Ok(variable)
+            Expr::Variable(name) => match context.get_variable(name) {
+                Some(variable) => Ok(variable.get()),
+                None => Err(context.variable_not_found(name)),
+            },
+            Expr::If {
+                cond,
+                then_expr,
+                else_expr,
+            } => {
+                if cond.eval(context)?.to_bool()? {
+                    then_expr.eval(context)
+                } else {
+                    else_expr.eval(context)
+                }
             }
             Expr::PrevAnswer => context.get_prev_answer().ok_or(EvalError::NoHistory),
+            Expr::Error => unreachable!("expressions with syntax errors are never evaluated"),
+            Expr::Spanned { expr, span } => expr.eval(context).map_err(|err| err.at(*span)),
+        }
+    }
+
+    /// Whether the expression is a number followed by units, such as `3 km`,
+    /// `3 km/h` or `9.81 m/s^2 * 70 kg`.
+    fn is_quantity_literal(&self) -> bool {
+        match self {
+            Expr::Spanned { expr, .. } => expr.is_quantity_literal(),
+            Expr::InfixOp {
+                op: InfixOp::Mul,
+                lhs,
+                ..
+            } if lhs.is_number() => true,
+            Expr::InfixOp {
+                op: InfixOp::Mul | InfixOp::Div,
+                lhs,
+                ..
+            } => lhs.is_quantity_literal(),
+            _ => false,
+        }
+    }
+
+    fn is_number(&self) -> bool {
+        match self {
+            Expr::Spanned { expr, .. }
+            | Expr::PrefixOp {
+                op: PrefixOp::Neg,
+                arg: expr,
+            } => expr.is_number(),
+            Expr::Int(_) | Expr::Float(_) => true,
+            _ => false,
+        }
+    }
+
+    /// Evaluates a quantity literal or the right-hand side of `to`, where
+    /// names that are not variables are units. Elsewhere an undefined name is
+    /// an error even if it names a unit, so that typos are not read as units.
+    fn eval_with_units(&self, context: &mut Context) -> Result<Value, EvalError> {
+        match self {
+            Expr::Spanned { expr, span } => {
+                expr.eval_with_units(context).map_err(|err| err.at(*span))
+            }
+            Expr::Variable(name) if context.get_variable(name).is_none() => context
+                .get_unit(name)
+                .map(|unit| Value::quantity(1., unit))
+                .ok_or_else(|| context.variable_not_found(name)),
+            Expr::InfixOp { op, lhs, rhs } => match op {
+                InfixOp::Mul => lhs.eval_with_units(context)? * rhs.eval_with_units(context)?,
+                InfixOp::Div => lhs.eval_with_units(context)? / rhs.eval_with_units(context)?,
+                InfixOp::Pow => {
+                    let base = lhs.eval_with_units(context)?;
+                    base.pow(rhs.eval(context)?, context.complex())
+                }
+                _ => self.eval(context),
+            },
+            _ => self.eval(context),
+        }
+    }
+
+    /// Moves the spans of the expression `offset` bytes back, making them
+    /// relative to the part of the input that starts at `offset`.
+    pub fn rebase(&mut self, offset: usize) {
+        match self {
+            Expr::Spanned { expr, span } => {
+                *span = SimpleSpan::from(span.start - offset..span.end - offset);
+                expr.rebase(offset);
+            }
+            Expr::FnCall { args, .. } => args.iter_mut().for_each(|arg| arg.rebase(offset)),
+            Expr::PrefixOp { arg, .. } | Expr::PostfixOp { arg, .. } => arg.rebase(offset),
+            Expr::InfixOp { lhs, rhs, .. } => {
+                lhs.rebase(offset);
+                rhs.rebase(offset);
+            }
+            Expr::If {
+                cond,
+                then_expr,
+                else_expr,
+            } => {
+                cond.rebase(offset);
+                then_expr.rebase(offset);
+                else_expr.rebase(offset);
+            }
+            Expr::Int(_)
+            | Expr::Float(_)
+            | Expr::Quantity { .. }
+            | Expr::Variable(_)
+            | Expr::PrevAnswer
+            | Expr::Error => {}
+        }
+    }
+
+    /// Writes the expression back as evar source that parses to the same
+    /// expression, with only the parentheses precedence requires.
+    pub fn to_source(&self) -> String {
+        match self {
+            Expr::Int(n) => n.to_string(),
+            Expr::Float(x) if x.is_infinite() => {
+                String::from(if *x < 0. { "-1e999" } else { "1e999" })
+            }
+            Expr::Float(x) => format!("{:?}", x),
+            Expr::Quantity { magnitude, unit } if magnitude.is_finite() => {
+                format!("{}{}", magnitude, unit)
+            }
+            Expr::Quantity { magnitude, unit } => {
+                format!("{}{}", Expr::Float(*magnitude).to_source(), unit)
+            }
+            Expr::Variable(name) => name.clone(),
+            Expr::FnCall { name, args } => {
+                let args = args.iter().map(Expr::to_source).collect::<Vec<_>>();
+                format!("{}({})", name, args.join(", "))
+            }
+            Expr::PrefixOp { op, arg } => {
+                let level = match op {
+                    PrefixOp::Neg | PrefixOp::BitNot => 13,
+                    PrefixOp::Not => 3,
+                };
+                format!("{}{}", op, arg.operand(level))
+            }
+            Expr::PostfixOp { op, arg } => format!("{}{}", arg.operand(15), op),
+            Expr::InfixOp { op, lhs, rhs } => {
+                use InfixOp::*;
+                let (left, right) = match op {
+                    Or => (1, 2),
+                    And => (2, 3),
+                    Eq | Ne | Lt | Le | Gt | Ge => (5, 5),
+                    Convert => (5, 11),
+                    BitOr => (6, 7),
+                    BitXor => (7, 8),
+                    BitAnd => (8, 9),
+                    Shl | Shr => (9, 10),
+                    Add | Sub => (10, 11),
+                    Mul | Div | IntDiv | Rem => (11, 12),
+                    Pow => (14, 13),
+                };
+                if *op == Pow {
+                    format!("{}^{}", lhs.operand(left), rhs.operand(right))
+                } else {
+                    format!("{} {} {}", lhs.operand(left), op, rhs.operand(right))
+                }
+            }
+            Expr::If {
+                cond,
+                then_expr,
+                else_expr,
+            } => format!(
+                "if {} then {} else {}",
+                cond.to_source(),
+                then_expr.to_source(),
+                else_expr.to_source()
+            ),
+            Expr::PrevAnswer => String::from("_"),
+            Expr::Error => String::from("<error>"),
+            Expr::Spanned { expr, .. } => expr.to_source(),
+        }
+    }
+
+    /// Source of the expression as an operand that must bind at least as
+    /// tightly as `level`, parenthesized if it does not.
+    fn operand(&self, level: u8) -> String {
+        if self.precedence() < level {
+            format!("({})", self.to_source())
+        } else {
+            self.to_source()
+        }
+    }
+
+    /// How tightly the outermost operator binds, following the levels of the
+    /// parser from `if` (0) up to atoms (15).
+    fn precedence(&self) -> u8 {
+        use InfixOp::*;
+        match self {
+            Expr::Spanned { expr, .. } => expr.precedence(),
+            Expr::If { .. } => 0,
+            Expr::InfixOp { op, .. } => match op {
+                Or => 1,
+                And => 2,
+                Eq | Ne | Lt | Le | Gt | Ge => 4,
+                Convert => 5,
+                BitOr => 6,
+                BitXor => 7,
+                BitAnd => 8,
+                Shl | Shr => 9,
+                Add | Sub => 10,
+                Mul | Div | IntDiv | Rem => 11,
+                Pow => 13,
+            },
+            Expr::PrefixOp {
+                op: PrefixOp::Not, ..
+            } => 3,
+            Expr::PrefixOp {
+                op: PrefixOp::Neg | PrefixOp::BitNot,
+                ..
+            } => 12,
+            Expr::Int(n) if n.sign() == num_bigint::Sign::Minus => 12,
+            Expr::Float(x) if x.is_sign_negative() => 12,
+            Expr::PostfixOp { .. } => 14,
+            _ => 15,
         }
     }
 }
@@ -105,18 +450,20 @@ impl Expr {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::{args::AngleUnit::*, create_context};
+    use crate::{create_context, lex_and_parse, models::AngleUnit::*, models::Stmt};
+    use num_complex::Complex64;
+    use num_rational::BigRational;
 
     #[test]
     fn test_number() {
-        let mut context = create_context(&Radian);
+        let mut context = create_context(&Radian, false);
         let expr = Expr::Float(42.0);
         assert_eq!(expr.eval(&mut context,).unwrap(), Value::from(42.0));
     }
 
     #[test]
     fn test_infix_op_add() {
-        let mut context = create_context(&Radian);
+        let mut context = create_context(&Radian, false);
         let expr = Expr::InfixOp {
             op: InfixOp::Add,
             lhs: Box::new(Expr::Float(1.0)),
@@ -127,7 +474,7 @@ mod tests {
 
     #[test]
     fn test_prefix_op_neg() {
-        let mut context = create_context(&Radian);
+        let mut context = create_context(&Radian, false);
         let expr = Expr::PrefixOp {
             op: PrefixOp::Neg,
             arg: Box::new(Expr::Float(5.0)),
@@ -137,17 +484,315 @@ mod tests {
 
     #[test]
     fn test_postfix_op_fac() {
-        let mut context = create_context(&Radian);
+        let mut context = create_context(&Radian, false);
         let expr = Expr::PostfixOp {
             op: PostfixOp::Fac,
-            arg: Box::new(Expr::Int(5)),
+            arg: Box::new(Expr::Int(5.into())),
         };
         assert_eq!(expr.eval(&mut context,).unwrap(), Value::from(120));
     }
 
+    #[test]
+    fn test_big_int() {
+        let mut context = create_context(&Radian, false);
+        let expr = Expr::PostfixOp {
+            op: PostfixOp::Fac,
+            arg: Box::new(Expr::Int(13.into())),
+        };
+        assert_eq!(
+            expr.eval(&mut context).unwrap(),
+            Value::from(BigInt::from(6227020800_u64))
+        );
+
+        let expr = Expr::InfixOp {
+            op: InfixOp::Pow,
+            lhs: Box::new(Expr::Int(2.into())),
+            rhs: Box::new(Expr::Int(40.into())),
+        };
+        assert_eq!(
+            expr.eval(&mut context).unwrap(),
+            Value::from(BigInt::from(1_u64 << 40))
+        );
+    }
+
+    #[test]
+    fn test_big_int_limits() {
+        let mut context = create_context(&Radian, false);
+        let mut eval = |input: &str| lex_and_parse(input).unwrap().eval(&mut context);
+
+        assert!(eval("2^200000").is_ok());
+        assert!(eval("10000!").is_ok());
+        assert_eq!(eval("1^4000000000").unwrap(), Value::from(1));
+        assert_eq!(eval("(-1)^4000000001").unwrap(), Value::from(-1));
+        for input in [
+            "2^4000000000",
+            "3^300000",
+            "(1/2)^300000",
+            "2^(-4000000000)",
+            "4000000000!",
+            "30000!",
+        ] {
+            assert!(
+                matches!(eval(input), Err(err) if matches!(err.root(), EvalError::Overflow)),
+                "{}",
+                input
+            );
+        }
+    }
+
+    #[test]
+    fn test_rational() {
+        let mut context = create_context(&Radian, false);
+        let third = || Expr::InfixOp {
+            op: InfixOp::Div,
+            lhs: Box::new(Expr::Int(1.into())),
+            rhs: Box::new(Expr::Int(3.into())),
+        };
+        let expr = Expr::InfixOp {
+            op: InfixOp::Add,
+            lhs: Box::new(Expr::InfixOp {
+                op: InfixOp::Add,
+                lhs: Box::new(third()),
+                rhs: Box::new(third()),
+            }),
+            rhs: Box::new(third()),
+        };
+        assert_eq!(expr.eval(&mut context).unwrap(), Value::from(1));
+
+        let expr = Expr::InfixOp {
+            op: InfixOp::Pow,
+            lhs: Box::new(third()),
+            rhs: Box::new(Expr::Int(2.into())),
+        };
+        assert_eq!(
+            expr.eval(&mut context).unwrap(),
+            Value::from(BigRational::new(1.into(), 9.into()))
+        );
+
+        let expr = Expr::FnCall {
+            name: "frac".to_string(),
+            args: vec![Expr::Float(0.25)],
+        };
+        assert_eq!(
+            expr.eval(&mut context).unwrap(),
+            Value::from(BigRational::new(1.into(), 4.into()))
+        );
+    }
+
+    #[test]
+    fn test_rational_exponent() {
+        let mut context = create_context(&Radian, false);
+        let mut eval = |input: &str| lex_and_parse(input).unwrap().eval(&mut context);
+
+        assert_eq!(eval("4^(1/2)").unwrap(), Value::from(2.0));
+        assert_eq!(eval("(1/4)^(1/2)").unwrap(), Value::from(0.5));
+        assert_eq!(eval("8^(-1/3)").unwrap(), Value::from(0.5));
+        assert!(eval("(-1)^(1/2)").unwrap().to_float().unwrap().is_nan());
+
+        let mut context = create_context(&Radian, true);
+        let z = lex_and_parse("(-4)^(1/2)")
+            .unwrap()
+            .eval(&mut context)
+            .unwrap()
+            .to_complex()
+            .unwrap();
+        assert!((z - Complex64::new(0., 2.)).norm() < 1e-12);
+    }
+
+    #[test]
+    fn test_log_domain() {
+        let mut context = create_context(&Radian, false);
+        let err = lex_and_parse("log(-1, 10)")
+            .unwrap()
+            .eval(&mut context)
+            .unwrap_err();
+        assert!(
+            matches!(err.root(), EvalError::MathDomain(msg) if msg == "the domain of log is (0, infinity) x R")
+        );
+    }
+
+    #[test]
+    fn test_complex() {
+        let sqrt_of_minus_one = Expr::FnCall {
+            name: "sqrt".to_string(),
+            args: vec![Expr::PrefixOp {
+                op: PrefixOp::Neg,
+                arg: Box::new(Expr::Int(1.into())),
+            }],
+        };
+
+        let mut context = create_context(&Radian, false);
+        assert!(matches!(
+            sqrt_of_minus_one.eval(&mut context),
+            Err(EvalError::MathDomain(_))
+        ));
+
+        let mut context = create_context(&Radian, true);
+        assert_eq!(
+            sqrt_of_minus_one.eval(&mut context).unwrap(),
+            Value::from(Complex64::i())
+        );
+
+        let expr = Expr::InfixOp {
+            op: InfixOp::Pow,
+            lhs: Box::new(Expr::Variable("i".to_string())),
+            rhs: Box::new(Expr::Int(2.into())),
+        };
+        assert_eq!(expr.eval(&mut context).unwrap(), Value::from(-1.0));
+
+        let mut eval = |input: &str| {
+            let value = lex_and_parse(input).unwrap().eval(&mut context).unwrap();
+            value.to_complex().unwrap()
+        };
+        let close = |z: Complex64, re: f64, im: f64| (z - Complex64::new(re, im)).norm() < 1e-12;
+        assert!(close(eval("(-1)^0.5"), 0., 1.));
+        assert!(close(eval("pow(-8, 1/3)"), 1., 3_f64.sqrt()));
+        assert!(close(
+            eval("log(-100, 10)"),
+            2.,
+            std::f64::consts::PI / 10_f64.ln()
+        ));
+        assert!(close(eval("log(8, 2)"), 3., 0.));
+    }
+
+    #[test]
+    fn test_units() {
+        let mut context = create_context(&Radian, false);
+        let quantity = |n: i32, unit: &str| Expr::InfixOp {
+            op: InfixOp::Mul,
+            lhs: Box::new(Expr::Int(n.into())),
+            rhs: Box::new(Expr::Variable(unit.to_string())),
+        };
+
+        let expr = Expr::InfixOp {
+            op: InfixOp::Convert,
+            lhs: Box::new(Expr::InfixOp {
+                op: InfixOp::Add,
+                lhs: Box::new(quantity(1, "km")),
+                rhs: Box::new(quantity(500, "m")),
+            }),
+            rhs: Box::new(Expr::Variable("m".to_string())),
+        };
+        assert_eq!(
+            expr.eval(&mut context).unwrap(),
+            Value::quantity(1500., context.get_unit("m").unwrap())
+        );
+
+        let expr = Expr::InfixOp {
+            op: InfixOp::Div,
+            lhs: Box::new(quantity(3, "km")),
+            rhs: Box::new(quantity(3000, "m")),
+        };
+        assert_eq!(expr.eval(&mut context).unwrap(), Value::from(1.0));
+
+        let expr = Expr::InfixOp {
+            op: InfixOp::Add,
+            lhs: Box::new(quantity(3, "km")),
+            rhs: Box::new(quantity(20, "min")),
+        };
+        assert!(matches!(
+            expr.eval(&mut context),
+            Err(EvalError::IncompatibleUnits(..))
+        ));
+    }
+
+    #[test]
+    fn test_unit_names() {
+        let mut context = create_context(&Radian, false);
+        let mut eval = |input: &str| lex_and_parse(input).unwrap().eval(&mut context);
+
+        // units are only read after a number and after `to`
+        assert_eq!(eval("2 g").unwrap().to_string(), "2 g");
+        assert_eq!(eval("-3 km/h").unwrap().to_string(), "-3 km/h");
+        assert_eq!(
+            eval("9.81 m/s^2 * 2 kg to N").unwrap().to_string(),
+            "19.62 N"
+        );
+        assert_eq!(eval("(1 m)^2 to cm^2").unwrap().to_string(), "10000 cm^2");
+        for input in ["g", "s + 1", "2^10 h", "sqrt(2) m"] {
+            assert!(
+                matches!(
+                    eval(input),
+                    Err(err) if matches!(err.root(), EvalError::VariableNotFound(..))
+                ),
+                "{}",
+                input
+            );
+        }
+        assert_eq!(
+            eval("h").unwrap_err().help(),
+            Some("`h` is a unit, write it after a number as in `1 h`")
+        );
+
+        // variables shadow units, also after a number
+        eval("let h = 4").unwrap();
+        assert_eq!(eval("h").unwrap(), Value::from(4));
+        assert_eq!(eval("2 h").unwrap(), Value::from(8));
+    }
+
+    #[test]
+    fn test_unit_exponent_overflow() {
+        let mut context = create_context(&Radian, false);
+        let mut eval = |input: &str| lex_and_parse(input).unwrap().eval(&mut context);
+
+        let m256 = eval("(1 m)^256").unwrap();
+        assert_eq!(m256.type_name(), "Quantity");
+        assert_eq!(m256.to_string(), "1 m^256");
+        assert_eq!(
+            eval("(1 m)^100 * (1 m)^100 to m^200").unwrap().to_string(),
+            "1 m^200"
+        );
+        assert!(matches!(
+            eval("(1 m)^256 + 1"),
+            Err(err) if matches!(err.root(), EvalError::IncompatibleUnits(..))
+        ));
+        for input in [
+            "(1 m)^2000000000 * (1 m)^2000000000",
+            "(1 m)^2000000000 / (1 m)^(-2000000000)",
+            "(1 N)^1200000000",
+            "(1 m)^3000000000",
+        ] {
+            assert!(
+                matches!(eval(input), Err(err) if matches!(err.root(), EvalError::Overflow)),
+                "{}",
+                input
+            );
+        }
+    }
+
+    #[test]
+    fn test_conditional() {
+        let mut context = create_context(&Radian, false);
+        let division_by_zero = Expr::InfixOp {
+            op: InfixOp::Div,
+            lhs: Box::new(Expr::Int(1.into())),
+            rhs: Box::new(Expr::Int(0.into())),
+        };
+        let expr = Expr::If {
+            cond: Box::new(Expr::InfixOp {
+                op: InfixOp::Lt,
+                lhs: Box::new(Expr::Int(1.into())),
+                rhs: Box::new(Expr::Float(1.5)),
+            }),
+            then_expr: Box::new(Expr::Int(1.into())),
+            else_expr: Box::new(division_by_zero),
+        };
+        assert_eq!(expr.eval(&mut context).unwrap(), Value::from(1));
+
+        let expr = Expr::If {
+            cond: Box::new(Expr::Int(1.into())),
+            then_expr: Box::new(Expr::Int(1.into())),
+            else_expr: Box::new(Expr::Int(2.into())),
+        };
+        assert!(matches!(
+            expr.eval(&mut context),
+            Err(EvalError::TypeError(..))
+        ));
+    }
+
     #[test]
     fn test_fn_call() {
-        let mut context = create_context(&Radian);
+        let mut context = create_context(&Radian, false);
         let expr = Expr::FnCall {
             name: "mock_fn".to_string(),
             args: vec![Expr::Float(2.0), Expr::Float(3.0)],
@@ -160,7 +805,174 @@ mod tests {
                 lhs: Expr::Variable(String::from("x")).into(),
                 rhs: Expr::Variable(String::from("y")).into(),
             },
+            String::from("x + y"),
+            None,
         );
         assert_eq!(expr.eval(&mut context,).unwrap(), Value::from(5.0));
     }
+
+    #[test]
+    fn test_angle_unit() {
+        let mut context = create_context(&Radian, false);
+        let sin = |x: f64| Expr::FnCall {
+            name: "sin".to_string(),
+            args: vec![Expr::Float(x)],
+        };
+        let asin = Expr::FnCall {
+            name: "asin".to_string(),
+            args: vec![Expr::Float(1.0)],
+        };
+
+        let angle = |x: f64, unit: &str, context: &Context| {
+            Value::quantity(x, context.get_unit(unit).unwrap())
+        };
+
+        assert_eq!(sin(0.0).eval(&mut context).unwrap(), Value::from(0.0));
+        assert_eq!(
+            asin.eval(&mut context).unwrap(),
+            angle(std::f64::consts::FRAC_PI_2, "rad", &context)
+        );
+
+        context.set_angle_unit(Degrees);
+        assert!((sin(90.0).eval(&mut context).unwrap().to_float().unwrap() - 1.0).abs() < 1e-12);
+        assert_eq!(
+            asin.eval(&mut context).unwrap(),
+            angle(90.0, "deg", &context)
+        );
+
+        context.set_angle_unit(Gradians);
+        assert!((sin(100.0).eval(&mut context).unwrap().to_float().unwrap() - 1.0).abs() < 1e-12);
+        assert_eq!(
+            asin.eval(&mut context).unwrap(),
+            angle(100.0, "grad", &context)
+        );
+    }
+
+    #[test]
+    fn test_angle_literals() {
+        let mut context = create_context(&Degrees, false);
+        let sin = |magnitude: f64, unit: &str| Expr::FnCall {
+            name: "sin".to_string(),
+            args: vec![Expr::Quantity {
+                magnitude,
+                unit: unit.to_string(),
+            }],
+        };
+
+        let half_pi = sin(std::f64::consts::FRAC_PI_2, "rad");
+        assert_eq!(half_pi.eval(&mut context).unwrap(), Value::from(1.0));
+
+        context.set_angle_unit(Radian);
+        let right_angle = sin(90.0, "deg");
+        assert_eq!(right_angle.eval(&mut context).unwrap(), Value::from(1.0));
+    }
+
+    #[test]
+    fn test_angles_as_numbers() {
+        for angle_unit in [Radian, Degrees] {
+            let mut context = create_context(&angle_unit, false);
+            let mut eval = |input: &str| lex_and_parse(input).unwrap().eval(&mut context);
+            let half_pi = std::f64::consts::FRAC_PI_2;
+
+            assert_eq!(eval("asin(1) + 1").unwrap(), Value::from(half_pi + 1.));
+            assert_eq!(eval("1 - acos(0)").unwrap(), Value::from(1. - half_pi));
+            assert_eq!(eval("asin(1) == pi/2").unwrap(), Value::from(true));
+            assert_eq!(eval("atan(1) < 1").unwrap(), Value::from(true));
+            assert_eq!(eval("float(asin(1))").unwrap(), Value::from(half_pi));
+            assert_eq!(
+                eval("exp(atan(1))").unwrap(),
+                Value::from(half_pi.exp().sqrt())
+            );
+            assert_eq!(eval("deg(asin(1))").unwrap(), Value::from(90.));
+            assert_eq!(eval("sqrt(asin(1))").unwrap(), Value::from(half_pi.sqrt()));
+            assert_eq!(eval("asin(1)^0.5").unwrap(), Value::from(half_pi.sqrt()));
+            assert_eq!(eval("asin(1) * 2").unwrap().type_name(), "Quantity");
+            let err = eval("1 m + asin(1)").unwrap_err();
+            assert!(matches!(err.root(), EvalError::IncompatibleUnits(..)));
+        }
+    }
+
+    #[test]
+    fn test_to_source() {
+        let parse = |input: &str| match lex_and_parse(input).unwrap() {
+            Stmt::Expr(expr) => expr,
+            _ => panic!("not an expression"),
+        };
+
+        for (input, source) in [
+            ("1 + 2 * 3", "1 + 2 * 3"),
+            ("(1 + 2) * 3", "(1 + 2) * 3"),
+            ("1 - (2 - 3)", "1 - (2 - 3)"),
+            ("(1 - 2) - 3", "1 - 2 - 3"),
+            ("2 ^ 3 ^ 4", "2^3^4"),
+            ("(2 ^ 3) ^ 4", "(2^3)^4"),
+            ("-x ^ 2", "-x^2"),
+            ("(-x) ^ 2", "(-x)^2"),
+            ("2 * -3", "2 * -3"),
+            ("(n + 1)!", "(n + 1)!"),
+            ("2 pi r", "2 * pi * r"),
+            ("sin(x + 1, 2) / 2.5", "sin(x + 1, 2) / 2.5"),
+            ("1.0e-7 + 3.", "1e-7 + 3.0"),
+            ("30deg + 1.5rad", "30deg + 1.5rad"),
+            ("1e999deg", "1e999deg"),
+            ("-2e400 rad", "-1e999rad"),
+            ("3 km + 2 m to m", "3 * km + 2 * m to m"),
+            ("not (a and b) or c", "not (a and b) or c"),
+            ("(1 < 2) == true", "(1 < 2) == true"),
+            ("1 + (if x then 1 else 2)", "1 + (if x then 1 else 2)"),
+            (
+                "if a then b else if c then d else _",
+                "if a then b else if c then d else _",
+            ),
+            ("a | b xor c & d", "a | b xor c & d"),
+            ("(a | b) & ~c", "(a | b) & ~c"),
+            ("1 << n + 1", "1 << n + 1"),
+            ("(1 << n) + 1", "(1 << n) + 1"),
+            ("x >> 2 == 0", "x >> 2 == 0"),
+        ] {
+            let expr = parse(input);
+            assert_eq!(expr.to_source(), source, "source of {}", input);
+            assert_eq!(parse(source), expr, "round trip of {}", input);
+        }
+    }
+
+    #[test]
+    fn test_bitwise() {
+        let mut context = create_context(&Radian, false);
+        let mut eval = |input: &str| lex_and_parse(input).unwrap().eval(&mut context);
+
+        assert_eq!(eval("0xF0 | 0x0F").unwrap(), Value::from(0xFF));
+        assert_eq!(eval("0b1100 & 0b1010").unwrap(), Value::from(0b1000));
+        assert_eq!(eval("0o17 xor 0b101").unwrap(), Value::from(0b1010));
+        assert_eq!(eval("~0").unwrap(), Value::from(-1));
+        assert_eq!(eval("1 << 4 + 1").unwrap(), Value::from(32));
+        assert_eq!(eval("-7 >> 1").unwrap(), Value::from(-4));
+        assert_eq!(
+            eval("1 << 100").unwrap(),
+            Value::from(BigInt::from(1) << 100)
+        );
+
+        assert!(matches!(
+            eval("2.5 & 1").map_err(EvalError::without_span),
+            Err(EvalError::TypeError(..))
+        ));
+        assert!(matches!(
+            eval("~1e3").map_err(EvalError::without_span),
+            Err(EvalError::TypeError(..))
+        ));
+        assert!(matches!(
+            eval("1 >> -1").map_err(EvalError::without_span),
+            Err(EvalError::MathDomain(_))
+        ));
+        assert!(matches!(
+            eval("1 << 4000000000").map_err(EvalError::without_span),
+            Err(EvalError::Overflow)
+        ));
+        assert!(matches!(
+            eval("(1 << 200000) << 100000").map_err(EvalError::without_span),
+            Err(EvalError::Overflow)
+        ));
+        assert_eq!(eval("0 << 4000000000").unwrap(), Value::from(0));
+        assert_eq!(eval("1 >> 4000000000").unwrap(), Value::from(0));
+    }
 }
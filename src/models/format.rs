@@ -0,0 +1,359 @@
+use num_bigint::BigInt;
+use num_traits::{One, Signed};
+
+/// Names of the display modes, without bit widths.
+pub const DISPLAY_MODES: [&str; 7] = ["dec", "hex", "bin", "oct", "sci", "eng", "si"];
+
+/// Largest bit width accepted for two's complement output.
+const MAX_WIDTH: u32 = 4096;
+
+/// SI prefixes from 10^-30 to 10^30, in steps of 10^3.
+const SI_PREFIXES: [&str; 21] = [
+    "q", "r", "y", "z", "a", "f", "p", "n", "u", "m", "", "k", "M", "G", "T", "P", "E", "Z", "Y",
+    "R", "Q",
+];
+
+/// How results are printed.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum DisplayMode {
+    #[default]
+    Decimal,
+    /// Integers in another radix. With a bit width, negative integers are
+    /// shown in two's complement.
+    Radix(Radix, Option<u32>),
+    /// `1.2345e4`
+    Scientific,
+    /// `12.345e3`, with an exponent that is a multiple of 3
+    Engineering,
+    /// `12.345k`
+    Si,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Radix {
+    Hex,
+    Binary,
+    Octal,
+}
+
+impl Radix {
+    fn base(self) -> u32 {
+        match self {
+            Radix::Hex => 16,
+            Radix::Binary => 2,
+            Radix::Octal => 8,
+        }
+    }
+
+    /// Number of bits one digit stands for.
+    fn bits(self) -> u32 {
+        match self {
+            Radix::Hex => 4,
+            Radix::Binary => 1,
+            Radix::Octal => 3,
+        }
+    }
+
+    fn prefix(self) -> &'static str {
+        match self {
+            Radix::Hex => "0x",
+            Radix::Binary => "0b",
+            Radix::Octal => "0o",
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Radix::Hex => "hex",
+            Radix::Binary => "bin",
+            Radix::Octal => "oct",
+        }
+    }
+}
+
+impl DisplayMode {
+    /// Whether the mode writes real numbers with an exponent.
+    pub(super) fn has_exponent(self) -> bool {
+        matches!(
+            self,
+            DisplayMode::Scientific | DisplayMode::Engineering | DisplayMode::Si
+        )
+    }
+}
+
+impl std::fmt::Display for DisplayMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DisplayMode::Decimal => write!(f, "dec"),
+            DisplayMode::Radix(radix, None) => write!(f, "{}", radix.name()),
+            DisplayMode::Radix(radix, Some(width)) => write!(f, "{}{}", radix.name(), width),
+            DisplayMode::Scientific => write!(f, "sci"),
+            DisplayMode::Engineering => write!(f, "eng"),
+            DisplayMode::Si => write!(f, "si"),
+        }
+    }
+}
+
+impl std::str::FromStr for DisplayMode {
+    type Err = String;
+
+    /// Parses a mode name such as `hex`, or a radix with a bit width such as
+    /// `bin16`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (name, width) = s.split_at(s.find(|c: char| c.is_ascii_digit()).unwrap_or(s.len()));
+        let radix = match name {
+            "hex" => Radix::Hex,
+            "bin" => Radix::Binary,
+            "oct" => Radix::Octal,
+            _ if !width.is_empty() => return Err(unknown_mode(s)),
+            "dec" => return Ok(DisplayMode::Decimal),
+            "sci" => return Ok(DisplayMode::Scientific),
+            "eng" => return Ok(DisplayMode::Engineering),
+            "si" => return Ok(DisplayMode::Si),
+            _ => return Err(unknown_mode(s)),
+        };
+        if width.is_empty() {
+            return Ok(DisplayMode::Radix(radix, None));
+        }
+        match width.parse() {
+            Ok(width) if (1..=MAX_WIDTH).contains(&width) => {
+                Ok(DisplayMode::Radix(radix, Some(width)))
+            }
+            _ => Err(format!("bit width must be in range 1-{}: {}", MAX_WIDTH, s)),
+        }
+    }
+}
+
+fn unknown_mode(s: &str) -> String {
+    format!(
+        "unknown display mode: {} (expected dec, hex, bin, oct, sci, eng or si)",
+        s
+    )
+}
+
+/// Formats an integer in `radix`. A negative integer that fits in `width`
+/// bits is shown in two's complement, and digits are padded to the width.
+pub(super) fn format_radix(n: &BigInt, radix: Radix, width: Option<u32>) -> String {
+    let fits = |width: u32| {
+        let min = -(BigInt::one() << (width - 1));
+        let max = BigInt::one() << width;
+        &min <= n && n < &max
+    };
+    match width {
+        Some(width) if fits(width) => {
+            let n = if n.is_negative() {
+                (BigInt::one() << width) + n
+            } else {
+                n.clone()
+            };
+            let digits = width.div_ceil(radix.bits()) as usize;
+            format!(
+                "{}{:0>digits$}",
+                radix.prefix(),
+                n.to_str_radix(radix.base())
+            )
+        }
+        _ => {
+            let sign = if n.is_negative() { "-" } else { "" };
+            format!(
+                "{}{}{}",
+                sign,
+                radix.prefix(),
+                n.abs().to_str_radix(radix.base())
+            )
+        }
+    }
+}
+
+/// A finite real number as decimal digits `d.ddd` times `10^exponent`.
+pub(super) struct Digits {
+    negative: bool,
+    digits: Vec<u8>,
+    exponent: i64,
+}
+
+impl Digits {
+    /// The shortest digits that read back as `x`, which must be finite.
+    pub(super) fn from_float(x: f64) -> Digits {
+        let formatted = format!("{:e}", x.abs());
+        let (mantissa, exponent) = formatted.split_once('e').expect("`{:e}` has an exponent");
+        Digits {
+            negative: x.is_sign_negative(),
+            digits: mantissa.bytes().filter(u8::is_ascii_digit).collect(),
+            exponent: exponent.parse().expect("`{:e}` has an integer exponent"),
+        }
+    }
+
+    pub(super) fn from_int(n: &BigInt) -> Digits {
+        let mut digits = n.abs().to_string().into_bytes();
+        let exponent = digits.len() as i64 - 1;
+        while digits.len() > 1 && digits.last() == Some(&b'0') {
+            digits.pop();
+        }
+        Digits {
+            negative: n.is_negative(),
+            digits,
+            exponent,
+        }
+    }
+
+    /// Rounds to `significant` digits, halves away from zero.
+    fn round(&mut self, significant: usize) {
+        if self.digits.len() <= significant {
+            return;
+        }
+        let round_up = self.digits[significant] >= b'5';
+        self.digits.truncate(significant);
+        if !round_up {
+            return;
+        }
+        for digit in self.digits.iter_mut().rev() {
+            if *digit == b'9' {
+                *digit = b'0';
+            } else {
+                *digit += 1;
+                return;
+            }
+        }
+        // all nines carried into the next power of ten
+        self.digits.insert(0, b'1');
+        self.exponent += 1;
+    }
+
+    /// The digits with `int_digits` of them before the decimal point, and
+    /// `fix` after it if given.
+    fn mantissa(&self, int_digits: usize, fix: Option<usize>) -> String {
+        let mut digits = self.digits.clone();
+        digits.resize(digits.len().max(int_digits + fix.unwrap_or(0)), b'0');
+        let (int_part, frac_part) = digits.split_at(int_digits);
+        let frac_part = match fix {
+            Some(fix) => &frac_part[..fix],
+            None => trim_zeros(frac_part),
+        };
+
+        let sign = if self.negative { "-" } else { "" };
+        let int_part = String::from_utf8_lossy(int_part);
+        if frac_part.is_empty() {
+            format!("{}{}", sign, int_part)
+        } else {
+            format!(
+                "{}{}.{}",
+                sign,
+                int_part,
+                String::from_utf8_lossy(frac_part)
+            )
+        }
+    }
+}
+
+fn trim_zeros(digits: &[u8]) -> &[u8] {
+    let len = digits.iter().rposition(|&d| d != b'0').map_or(0, |i| i + 1);
+    &digits[..len]
+}
+
+/// Formats a real number in scientific, engineering or SI notation, with
+/// `fix` digits after the decimal point if given.
+pub(super) fn format_exponent(mut digits: Digits, mode: DisplayMode, fix: Option<usize>) -> String {
+    if mode == DisplayMode::Scientific {
+        if let Some(fix) = fix {
+            digits.round(fix + 1);
+        }
+        return format!("{}e{}", digits.mantissa(1, fix), digits.exponent);
+    }
+
+    if let Some(fix) = fix {
+        let shift = digits.exponent.rem_euclid(3) as usize;
+        // a carry into the next power of ten only adds zeros
+        digits.round(fix + 1 + shift);
+    }
+    let shift = digits.exponent.rem_euclid(3);
+    let mantissa = digits.mantissa(shift as usize + 1, fix);
+    let exponent = digits.exponent - shift;
+
+    let prefix = (exponent / 3 + 10)
+        .try_into()
+        .ok()
+        .and_then(|i: usize| SI_PREFIXES.get(i));
+    match (mode, prefix) {
+        (DisplayMode::Si, Some(prefix)) => format!("{}{}", mantissa, prefix),
+        _ => format!("{}e{}", mantissa, exponent),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_mode() {
+        assert_eq!("hex".parse(), Ok(DisplayMode::Radix(Radix::Hex, None)));
+        assert_eq!(
+            "bin16".parse(),
+            Ok(DisplayMode::Radix(Radix::Binary, Some(16)))
+        );
+        assert_eq!("si".parse(), Ok(DisplayMode::Si));
+        assert!("sci8".parse::<DisplayMode>().is_err());
+        assert!("hex0".parse::<DisplayMode>().is_err());
+        assert!("roman".parse::<DisplayMode>().is_err());
+        for name in ["dec", "hex", "oct32", "sci", "eng", "si"] {
+            assert_eq!(name.parse::<DisplayMode>().unwrap().to_string(), name);
+        }
+    }
+
+    #[test]
+    fn test_format_radix() {
+        let radix = |n: i64, radix, width| format_radix(&BigInt::from(n), radix, width);
+        assert_eq!(radix(255, Radix::Hex, None), "0xff");
+        assert_eq!(radix(-255, Radix::Hex, None), "-0xff");
+        assert_eq!(radix(10, Radix::Binary, None), "0b1010");
+        assert_eq!(radix(8, Radix::Octal, None), "0o10");
+        assert_eq!(radix(5, Radix::Binary, Some(8)), "0b00000101");
+        assert_eq!(radix(-1, Radix::Hex, Some(8)), "0xff");
+        assert_eq!(radix(-128, Radix::Hex, Some(8)), "0x80");
+        assert_eq!(radix(-1, Radix::Octal, Some(8)), "0o377");
+        // out of range for the width
+        assert_eq!(radix(-129, Radix::Hex, Some(8)), "-0x81");
+        assert_eq!(radix(256, Radix::Hex, Some(8)), "0x100");
+    }
+
+    #[test]
+    fn test_format_exponent() {
+        let float = |x: f64, mode, fix| format_exponent(Digits::from_float(x), mode, fix);
+        use DisplayMode::*;
+        assert_eq!(float(12345., Scientific, None), "1.2345e4");
+        assert_eq!(float(-0.00012, Scientific, None), "-1.2e-4");
+        assert_eq!(float(0., Scientific, None), "0e0");
+        assert_eq!(float(12345., Scientific, Some(2)), "1.23e4");
+        assert_eq!(float(99999., Scientific, Some(2)), "1.00e5");
+        assert_eq!(float(12345., Engineering, None), "12.345e3");
+        assert_eq!(float(0.00012, Engineering, None), "120e-6");
+        assert_eq!(float(999.96, Engineering, Some(1)), "1.0e3");
+        assert_eq!(float(4700., Si, None), "4.7k");
+        assert_eq!(float(0.0000022, Si, Some(2)), "2.20u");
+        assert_eq!(float(12., Si, None), "12");
+        assert_eq!(float(1e40, Si, None), "10e39");
+
+        let int =
+            |n: &str| format_exponent(Digits::from_int(&n.parse().unwrap()), Scientific, None);
+        assert_eq!(
+            int("265252859812191058636308480000000"),
+            "2.6525285981219105863630848e32"
+        );
+        assert_eq!(int("-100"), "-1e2");
+        assert_eq!(int("0"), "0e0");
+    }
+
+    #[test]
+    fn test_format_value() {
+        use super::super::Value;
+        use DisplayMode::*;
+        let hex = Radix(super::Radix::Hex, None);
+        assert_eq!(Value::from(255).format(None, hex), "0xff");
+        assert_eq!(Value::from(2.5).format(None, hex), "2.5");
+        assert_eq!(Value::from(2.5).format(Some(2), Scientific), "2.50e0");
+        assert_eq!(Value::from(f64::INFINITY).format(None, Si), "inf");
+        let third = (Value::from(1) / 3.into()).unwrap();
+        assert_eq!(third.format(None, Engineering), "333.3333333333333e-3");
+        assert_eq!(third.format(None, Decimal), "1/3");
+    }
+}
@@ -1,52 +1,159 @@
-use super::{Context, EvalError, Expr, Value};
+use super::{Context, EvalError, Expr, Frame, Value, Variable};
 use std::rc::Rc;
 
 #[derive(Clone)]
 pub struct Function(Rc<FunctionInner>);
 
 impl Function {
-    pub fn call(&self, args: Vec<Value>, context: &mut Context) -> Result<Value, EvalError> {
-        self.0.call(args, context)
+    /// Calls the function under the name it was called by, which errors in
+    /// user-defined functions report in their backtrace.
+    pub fn call(
+        &self,
+        name: &str,
+        args: Vec<Value>,
+        context: &mut Context,
+    ) -> Result<Value, EvalError> {
+        self.0.call(name, args, context)
     }
 
-    pub fn new_internal(arg_names: Vec<String>, body: Expr) -> Function {
+    /// Creates a user-defined function. `source` is the body as written and
+    /// `doc` the docstring from a trailing `#` comment.
+    pub fn new_internal(
+        arg_names: Vec<String>,
+        body: Expr,
+        source: String,
+        doc: Option<String>,
+    ) -> Function {
         Function(Rc::new(FunctionInner::Internal {
             arity: arg_names.len(),
             arg_names,
             body,
+            source,
+            doc,
         }))
     }
 
     pub fn new_external(
         arity: usize,
-        body: fn(Vec<Value>) -> Result<Value, EvalError>,
+        body: impl Fn(Vec<Value>) -> Result<Value, EvalError> + 'static,
     ) -> Function {
-        Function(Rc::new(FunctionInner::External { arity, body }))
+        Function::new_external_with_context(arity, move |args, _| body(args))
+    }
+
+    /// Creates a builtin that reads settings such as the angle unit from the
+    /// context it is called in.
+    pub fn new_external_with_context(
+        arity: usize,
+        body: impl Fn(Vec<Value>, &Context) -> Result<Value, EvalError> + 'static,
+    ) -> Function {
+        Function(Rc::new(FunctionInner::External {
+            arity,
+            doc: None,
+            body: Rc::new(body),
+        }))
+    }
+
+    /// Attaches documentation to a builtin.
+    pub fn with_doc(self, doc: Doc) -> Function {
+        match &*self.0 {
+            FunctionInner::External { arity, body, .. } => {
+                Function(Rc::new(FunctionInner::External {
+                    arity: *arity,
+                    doc: Some(doc),
+                    body: body.clone(),
+                }))
+            }
+            FunctionInner::Internal { .. } => self,
+        }
     }
 
     pub fn is_external(&self) -> bool {
-        matches!(&*self.0, FunctionInner::External { arity: _, body: _ })
+        matches!(&*self.0, FunctionInner::External { .. })
+    }
+
+    pub fn arity(&self) -> usize {
+        match &*self.0 {
+            FunctionInner::External { arity, .. } | FunctionInner::Internal { arity, .. } => *arity,
+        }
+    }
+
+    /// Parameter names of a user-defined function.
+    pub fn arg_names(&self) -> Option<&[String]> {
+        match &*self.0 {
+            FunctionInner::External { .. } => None,
+            FunctionInner::Internal { arg_names, .. } => Some(arg_names),
+        }
+    }
+
+    /// Body of a user-defined function.
+    pub fn body(&self) -> Option<&Expr> {
+        match &*self.0 {
+            FunctionInner::External { .. } => None,
+            FunctionInner::Internal { body, .. } => Some(body),
+        }
+    }
+
+    /// Body of a user-defined function as it was written.
+    pub fn source(&self) -> Option<&str> {
+        match &*self.0 {
+            FunctionInner::External { .. } => None,
+            FunctionInner::Internal { source, .. } => Some(source),
+        }
+    }
+
+    /// Documentation of a builtin.
+    pub fn doc(&self) -> Option<&Doc> {
+        match &*self.0 {
+            FunctionInner::External { doc, .. } => doc.as_ref(),
+            FunctionInner::Internal { .. } => None,
+        }
+    }
+
+    /// Docstring of a user-defined function.
+    pub fn docstring(&self) -> Option<&str> {
+        match &*self.0 {
+            FunctionInner::External { .. } => None,
+            FunctionInner::Internal { doc, .. } => doc.as_deref(),
+        }
     }
 }
 
+/// Documentation of a builtin function, shown by `help NAME`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Doc {
+    pub signature: &'static str,
+    pub domain: &'static str,
+    pub description: &'static str,
+}
+
+type NativeFn = dyn Fn(Vec<Value>, &Context) -> Result<Value, EvalError>;
+
 enum FunctionInner {
     External {
         arity: usize,
-        body: fn(Vec<Value>) -> Result<Value, EvalError>,
+        doc: Option<Doc>,
+        body: Rc<NativeFn>,
     },
     Internal {
         arity: usize,
         arg_names: Vec<String>,
         body: Expr,
+        source: String,
+        doc: Option<String>,
     },
 }
 
 impl FunctionInner {
-    pub fn call(&self, args: Vec<Value>, context: &mut Context) -> Result<Value, EvalError> {
+    pub fn call(
+        &self,
+        name: &str,
+        args: Vec<Value>,
+        context: &mut Context,
+    ) -> Result<Value, EvalError> {
         match self {
-            FunctionInner::External { arity, body } => {
+            FunctionInner::External { arity, body, .. } => {
                 if args.len() == *arity {
-                    body(args)
+                    body(args, context)
                 } else {
                     Err(EvalError::InvalidNumberOfArguments(*arity, args.len()))
                 }
@@ -55,14 +162,28 @@ impl FunctionInner {
                 arity,
                 arg_names,
                 body,
+                source,
+                ..
             } => {
                 if args.len() == *arity {
-                    context.extend();
-
-                    for (arg_name, arg) in arg_names.iter().zip(args.into_iter()) {
-                        context.set_variable(arg_name, arg);
-                    }
-                    body.eval(context)
+                    let frame = arg_names
+                        .iter()
+                        .cloned()
+                        .zip(args.iter().cloned().map(Variable::Internal))
+                        .collect();
+                    context.with_frame(frame, |context| {
+                        body.eval(context).map_err(|err| {
+                            // spans in the body point into its source, not
+                            // into the input being evaluated
+                            let expr = err.span().and_then(|span| source.get(span));
+                            let frame = Frame {
+                                function: name.to_string(),
+                                args: arg_names.iter().cloned().zip(args).collect(),
+                                expr: expr.map(str::to_string),
+                            };
+                            err.without_span().in_call(frame)
+                        })
+                    })
                 } else {
                     Err(EvalError::InvalidNumberOfArguments(*arity, args.len()))
                 }
@@ -70,3 +191,88 @@ impl FunctionInner {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{create_context, lex_and_parse, models::AngleUnit::*};
+
+    fn eval(input: &str, context: &mut Context) -> Result<Value, EvalError> {
+        lex_and_parse(input).unwrap().eval(context)
+    }
+
+    #[test]
+    fn test_arguments_do_not_leak() {
+        let mut context = create_context(&Radian, false);
+        eval("let f(x) = x * 2", &mut context).unwrap();
+        assert_eq!(eval("f(3)", &mut context).unwrap(), Value::from(6));
+        assert!(context.get_variable("x").is_none());
+
+        eval("let g(x) = x / 0", &mut context).unwrap();
+        assert!(eval("g(3)", &mut context).is_err());
+        assert!(context.get_variable("x").is_none());
+    }
+
+    #[test]
+    fn test_nested_calls() {
+        let mut context = create_context(&Radian, false);
+        eval("let g(x) = x + 1", &mut context).unwrap();
+        eval("let f(x) = g(x * 2) + x", &mut context).unwrap();
+        assert_eq!(eval("f(3)", &mut context).unwrap(), Value::from(10));
+
+        // a callee cannot see the arguments of its caller
+        eval("let h(y) = x", &mut context).unwrap();
+        eval("let k(x) = h(1)", &mut context).unwrap();
+        assert!(matches!(
+            eval("k(1)", &mut context),
+            Err(err) if matches!(err.root(), EvalError::VariableNotFound(..))
+        ));
+    }
+
+    #[test]
+    fn test_recursive_call() {
+        let mut context = create_context(&Radian, false);
+        eval(
+            "let fact(n) = if n <= 1 then 1 else n * fact(n - 1)",
+            &mut context,
+        )
+        .unwrap();
+        assert_eq!(
+            eval("fact(10)", &mut context).unwrap(),
+            Value::from(3628800)
+        );
+        assert!(context.get_variable("n").is_none());
+    }
+
+    #[test]
+    fn test_stack_overflow() {
+        let mut context = create_context(&Radian, false);
+        context.set_max_depth(20);
+        eval("let f(n) = f(n + 1)", &mut context).unwrap();
+        assert!(matches!(
+            eval("f(0)", &mut context),
+            Err(err) if matches!(err.root(), EvalError::StackOverflow(20))
+        ));
+        assert!(context.get_variable("n").is_none());
+    }
+
+    #[test]
+    fn test_backtrace() {
+        let mut context = create_context(&Radian, false);
+        eval("let g(x) = 1 + x / 0", &mut context).unwrap();
+        eval("let f(x, y) = 2 * g(x + y)", &mut context).unwrap();
+
+        let err = eval("3 + f(1, 2)", &mut context).unwrap_err();
+        assert!(matches!(err.root(), EvalError::DivisionByZero));
+        assert_eq!(err.span(), Some(4..11));
+        let trace = err
+            .trace()
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            trace,
+            ["in g(x = 3) at `x / 0`", "in f(x = 1, y = 2) at `g(x + y)`"]
+        );
+    }
+}
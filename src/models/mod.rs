@@ -1,18 +1,22 @@
 mod context;
 mod errors;
 mod expression;
+mod format;
 mod function;
 pub mod operators;
 mod statement;
 pub mod token;
+mod unit;
 mod value;
 mod variable;
 
-pub use context::Context;
-pub use errors::{EvalError, SevaError};
+pub use context::{AngleUnit, Context, DEFAULT_MAX_DEPTH};
+pub use errors::{Error, EvalError, Frame, ParseError, SevaError};
 pub use expression::Expr;
-pub use function::Function;
+pub use format::{DISPLAY_MODES, DisplayMode, Radix};
+pub use function::{Doc, Function};
 pub use statement::Stmt;
 pub use token::Token;
+pub use unit::{BaseUnit, Dimension, Unit};
 pub use value::Value;
 pub use variable::Variable;
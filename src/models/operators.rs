@@ -1,12 +1,16 @@
 #[derive(Debug, PartialEq, Clone)]
 pub enum PrefixOp {
     Neg,
+    Not,
+    BitNot,
 }
 
 impl std::fmt::Display for PrefixOp {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         let op_str = match self {
             PrefixOp::Neg => "-",
+            PrefixOp::Not => "not ",
+            PrefixOp::BitNot => "~",
         };
         write!(f, "{}", op_str)
     }
@@ -35,6 +39,20 @@ pub enum InfixOp {
     IntDiv,
     Rem,
     Pow,
+    Convert,
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    And,
+    Or,
+    BitAnd,
+    BitOr,
+    BitXor,
+    Shl,
+    Shr,
 }
 
 impl std::fmt::Display for InfixOp {
@@ -47,6 +65,20 @@ impl std::fmt::Display for InfixOp {
             InfixOp::IntDiv => "//",
             InfixOp::Rem => "%",
             InfixOp::Pow => "^",
+            InfixOp::Convert => "to",
+            InfixOp::Eq => "==",
+            InfixOp::Ne => "!=",
+            InfixOp::Lt => "<",
+            InfixOp::Le => "<=",
+            InfixOp::Gt => ">",
+            InfixOp::Ge => ">=",
+            InfixOp::And => "and",
+            InfixOp::Or => "or",
+            InfixOp::BitAnd => "&",
+            InfixOp::BitOr => "|",
+            InfixOp::BitXor => "xor",
+            InfixOp::Shl => "<<",
+            InfixOp::Shr => ">>",
         };
         write!(f, "{}", op_str)
     }
@@ -1,4 +1,4 @@
-use super::{Context, EvalError, Expr, Value};
+use super::{Context, DisplayMode, EvalError, Expr, Value};
 
 #[derive(Debug, PartialEq)]
 pub enum Stmt {
@@ -10,8 +10,17 @@ pub enum Stmt {
         name: String,
         arg_names: Vec<String>,
         body: Expr,
+        /// The body as written.
+        source: String,
+        /// The text of a `#` comment after the body.
+        doc: Option<String>,
     },
     Expr(Expr),
+    /// A statement with an `as` suffix choosing how its result is printed.
+    Formatted {
+        stmt: Box<Stmt>,
+        mode: DisplayMode,
+    },
 }
 
 impl std::fmt::Display for Stmt {
@@ -22,13 +31,23 @@ impl std::fmt::Display for Stmt {
                 name,
                 arg_names,
                 body,
+                ..
             } => write!(f, "let {}({:?}) := {}", name, arg_names, body),
             Stmt::Expr(expr) => write!(f, "{}", expr),
+            Stmt::Formatted { stmt, mode } => write!(f, "{} as {}", stmt, mode),
         }
     }
 }
 
 impl Stmt {
+    /// The display mode chosen with an `as` suffix.
+    pub fn display_mode(&self) -> Option<DisplayMode> {
+        match self {
+            Stmt::Formatted { mode, .. } => Some(*mode),
+            _ => None,
+        }
+    }
+
     pub fn eval(self, context: &mut Context) -> Result<Value, EvalError> {
         match self {
             Stmt::DefVar { name, expr } => {
@@ -42,8 +61,10 @@ impl Stmt {
                 name,
                 arg_names: args,
                 body,
+                source,
+                doc,
             } => {
-                context.set_function(&name, args, body);
+                context.set_function(&name, args, body, source, doc);
                 Ok(Value::null())
             }
             Stmt::Expr(expr) => {
@@ -51,6 +72,7 @@ impl Stmt {
                 context.set_prev_answer(&answer);
                 Ok(answer)
             }
+            Stmt::Formatted { stmt, .. } => stmt.eval(context),
         }
     }
 }
@@ -59,11 +81,11 @@ impl Stmt {
 mod tests {
     use super::super::Expr;
     use super::*;
-    use crate::{args::AngleUnit::*, create_context};
+    use crate::{create_context, models::AngleUnit::*};
 
     #[test]
     fn test_def_var_eval() {
-        let mut context = create_context(&Radian);
+        let mut context = create_context(&Radian, false);
         let stmt = Stmt::DefVar {
             name: "x".to_string(),
             expr: Expr::Float(42.0),
@@ -74,7 +96,7 @@ mod tests {
 
     #[test]
     fn test_def_fun_eval() {
-        let mut context = create_context(&Radian);
+        let mut context = create_context(&Radian, false);
         let stmt = Stmt::DefFun {
             name: "add".to_string(),
             arg_names: vec!["a".to_string(), "b".to_string()],
@@ -83,6 +105,8 @@ mod tests {
                 lhs: Box::new(Expr::Variable("a".to_string())),
                 rhs: Box::new(Expr::Variable("b".to_string())),
             },
+            source: "a + b".to_string(),
+            doc: None,
         };
         assert!(stmt.eval(&mut context).is_ok());
         assert!(context.get_function("add").is_some());
@@ -90,7 +114,7 @@ mod tests {
 
     #[test]
     fn test_expr_eval() {
-        let mut context = create_context(&Radian);
+        let mut context = create_context(&Radian, false);
         let stmt = Stmt::Expr(Expr::Float(42.0));
         assert_eq!(stmt.eval(&mut context).unwrap(), Value::from(42.0));
     }
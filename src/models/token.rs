@@ -1,4 +1,5 @@
 use logos::Logos;
+use num_bigint::BigInt;
 
 #[derive(Logos, Clone, PartialEq, Debug)]
 pub enum Token<'a> {
@@ -7,16 +8,44 @@ pub enum Token<'a> {
     #[regex(r"\s+")]
     Space,
 
-    #[regex(r"\d+", |lex| lex.slice().parse::<i32>().unwrap())]
-    Int(i32),
+    #[regex(r"#[^\n]*", allow_greedy = true)]
+    Comment,
 
-    #[regex(r"\d+\.\d*", |lex| lex.slice().parse::<f64>().unwrap())]
-    #[regex(r"\d*\.\d+[eE][-+]?\d+", |lex| lex.slice().parse::<f64>().unwrap())]
-    #[regex(r"\d+[eE][-+]?\d+", |lex| lex.slice().parse::<f64>().unwrap())]
+    #[regex(r"\d+(_\d+)*", |lex| parse_int(lex.slice(), 10))]
+    #[regex(r"0x[[:xdigit:]]+(_[[:xdigit:]]+)*", |lex| parse_int(&lex.slice()[2..], 16))]
+    #[regex(r"0o[0-7]+(_[0-7]+)*", |lex| parse_int(&lex.slice()[2..], 8))]
+    #[regex(r"0b[01]+(_[01]+)*", |lex| parse_int(&lex.slice()[2..], 2))]
+    Int(BigInt),
+
+    #[regex(r"\d+(_\d+)*\.(\d+(_\d+)*)?", |lex| parse_float(lex.slice()))]
+    #[regex(r"(\d+(_\d+)*)?\.\d+(_\d+)*[eE][-+]?\d+", |lex| parse_float(lex.slice()))]
+    #[regex(r"\d+(_\d+)*[eE][-+]?\d+", |lex| parse_float(lex.slice()))]
     Float(f64),
 
+    #[regex(r#"(\d+(\.\d*)?|\.\d+)°((\d+(\.\d*)?|\.\d+)')?((\d+(\.\d*)?|\.\d+)")?"#, |lex| parse_angle(lex.slice()))]
+    Degrees(f64),
+
     #[token("let ")]
     Let,
+    #[token("to")]
+    #[token("in")]
+    To,
+    #[token("as")]
+    As,
+    #[token("if")]
+    If,
+    #[token("then")]
+    Then,
+    #[token("else")]
+    Else,
+    #[token("and")]
+    And,
+    #[token("or")]
+    Or,
+    #[token("not")]
+    Not,
+    #[token("xor")]
+    Xor,
     #[token("=")]
     Equal,
 
@@ -36,6 +65,28 @@ pub enum Token<'a> {
     Caret,
     #[token("!")]
     Exclamation,
+    #[token("==")]
+    DoubleEqual,
+    #[token("!=")]
+    ExclamationEqual,
+    #[token("<")]
+    Less,
+    #[token("<=")]
+    LessEqual,
+    #[token(">")]
+    Greater,
+    #[token(">=")]
+    GreaterEqual,
+    #[token("&")]
+    Ampersand,
+    #[token("|")]
+    Pipe,
+    #[token("~")]
+    Tilde,
+    #[token("<<")]
+    DoubleLess,
+    #[token(">>")]
+    DoubleGreater,
 
     #[token("(")]
     LParen,
@@ -55,8 +106,10 @@ impl std::fmt::Display for Token<'_> {
         match self {
             Self::Error => write!(f, "<unknown symbol>"),
             Self::Space => write!(f, " "),
+            Self::Comment => write!(f, "#"),
             Self::Int(s) => write!(f, "{}", s),
             Self::Float(s) => write!(f, "{}", s),
+            Self::Degrees(s) => write!(f, "{}°", s),
             Self::Plus => write!(f, "+"),
             Self::Minus => write!(f, "-"),
             Self::Asterisk => write!(f, "*"),
@@ -65,17 +118,63 @@ impl std::fmt::Display for Token<'_> {
             Self::Percent => write!(f, "%"),
             Self::Caret => write!(f, "^"),
             Self::Exclamation => write!(f, "!"),
+            Self::DoubleEqual => write!(f, "=="),
+            Self::ExclamationEqual => write!(f, "!="),
+            Self::Less => write!(f, "<"),
+            Self::LessEqual => write!(f, "<="),
+            Self::Greater => write!(f, ">"),
+            Self::GreaterEqual => write!(f, ">="),
+            Self::Ampersand => write!(f, "&"),
+            Self::Pipe => write!(f, "|"),
+            Self::Tilde => write!(f, "~"),
+            Self::DoubleLess => write!(f, "<<"),
+            Self::DoubleGreater => write!(f, ">>"),
             Self::LParen => write!(f, "("),
             Self::RParen => write!(f, ")"),
             Self::Comma => write!(f, ","),
             Self::Ident(s) => write!(f, "{}", s),
             Self::Let => write!(f, "let"),
+            Self::To => write!(f, "to"),
+            Self::As => write!(f, "as"),
+            Self::If => write!(f, "if"),
+            Self::Then => write!(f, "then"),
+            Self::Else => write!(f, "else"),
+            Self::And => write!(f, "and"),
+            Self::Or => write!(f, "or"),
+            Self::Not => write!(f, "not"),
+            Self::Xor => write!(f, "xor"),
             Self::Equal => write!(f, "="),
             Self::Underscore => write!(f, "_"),
         }
     }
 }
 
+/// Parses the digits of an integer literal in the given radix, ignoring `_`
+/// separators.
+fn parse_int(digits: &str, radix: u32) -> BigInt {
+    let digits = digits.replace('_', "");
+    BigInt::parse_bytes(digits.as_bytes(), radix).unwrap()
+}
+
+fn parse_float(literal: &str) -> f64 {
+    literal.replace('_', "").parse().unwrap()
+}
+
+/// Parses an angle literal such as `12°30'15"` into its magnitude in
+/// degrees. `30deg` and `1.2rad` are built by the parser, so that names such
+/// as `2radius` still lex as a number and an identifier.
+fn parse_angle(literal: &str) -> f64 {
+    let mut angle = 0.;
+    let mut rest = literal;
+    for (mark, divisor) in [('°', 1.), ('\'', 60.), ('"', 3600.)] {
+        if let Some((part, tail)) = rest.split_once(mark) {
+            angle += part.parse::<f64>().unwrap() / divisor;
+            rest = tail;
+        }
+    }
+    angle
+}
+
 pub fn lex(input: &str) -> impl Iterator<Item = (Token<'_>, std::ops::Range<usize>)> {
     Token::lexer(input).spanned().map(|(tok, span)| match tok {
         Ok(tok) => (tok, span),
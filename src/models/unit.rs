@@ -0,0 +1,210 @@
+use super::EvalError;
+use std::fmt;
+use std::ops::{Div, Mul};
+use std::rc::Rc;
+
+/// Exponents of the base dimensions: length, mass, time, electric current,
+/// temperature, amount of substance, luminous intensity and plane angle.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Dimension([i32; 8]);
+
+impl Dimension {
+    pub const LENGTH: Dimension = Dimension::base(0);
+    pub const MASS: Dimension = Dimension::base(1);
+    pub const TIME: Dimension = Dimension::base(2);
+    pub const CURRENT: Dimension = Dimension::base(3);
+    pub const TEMPERATURE: Dimension = Dimension::base(4);
+    pub const AMOUNT: Dimension = Dimension::base(5);
+    pub const LUMINOSITY: Dimension = Dimension::base(6);
+    pub const ANGLE: Dimension = Dimension::base(7);
+
+    const fn base(index: usize) -> Dimension {
+        let mut exponents = [0; 8];
+        exponents[index] = 1;
+        Dimension(exponents)
+    }
+
+    pub fn is_dimensionless(&self) -> bool {
+        self.0 == [0; 8]
+    }
+
+    pub fn powi(self, n: i32) -> Dimension {
+        self.checked_powi(n).expect("dimension exponent overflow")
+    }
+
+    pub fn checked_powi(self, n: i32) -> Option<Dimension> {
+        let mut exponents = self.0;
+        for e in &mut exponents {
+            *e = e.checked_mul(n)?;
+        }
+        Some(Dimension(exponents))
+    }
+
+    /// Multiplying quantities adds the exponents of their dimensions.
+    pub fn checked_mul(self, rhs: Dimension) -> Option<Dimension> {
+        let mut exponents = self.0;
+        for (e, r) in exponents.iter_mut().zip(rhs.0) {
+            *e = e.checked_add(r)?;
+        }
+        Some(Dimension(exponents))
+    }
+}
+
+impl Mul for Dimension {
+    type Output = Dimension;
+    fn mul(self, rhs: Dimension) -> Dimension {
+        self.checked_mul(rhs).expect("dimension exponent overflow")
+    }
+}
+
+impl Div for Dimension {
+    type Output = Dimension;
+    fn div(self, rhs: Dimension) -> Dimension {
+        self * rhs.powi(-1)
+    }
+}
+
+/// A named unit such as `km` or `mph`, with its size relative to the
+/// coherent SI unit of the same dimension.
+#[derive(Debug, PartialEq)]
+pub struct BaseUnit {
+    pub name: String,
+    pub dimension: Dimension,
+    pub scale: f64,
+}
+
+/// A product of powers of base units, e.g. `km/h` or `kg m/s^2`.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Unit(Vec<(Rc<BaseUnit>, i32)>);
+
+impl Unit {
+    pub fn new(base: Rc<BaseUnit>) -> Unit {
+        Unit(vec![(base, 1)])
+    }
+
+    pub fn dimension(&self) -> Dimension {
+        self.checked_dimension()
+            .expect("unit exponents are checked when units are combined")
+    }
+
+    fn checked_dimension(&self) -> Option<Dimension> {
+        self.0
+            .iter()
+            .try_fold(Dimension::default(), |acc, (base, exp)| {
+                acc.checked_mul(base.dimension.checked_powi(*exp)?)
+            })
+    }
+
+    /// Size of the unit in coherent SI units.
+    pub fn scale(&self) -> f64 {
+        self.0
+            .iter()
+            .map(|(base, exp)| base.scale.powi(*exp))
+            .product()
+    }
+
+    /// Multiplies two units. Factors on the right are expressed in a unit of
+    /// the same dimension on the left when there is one, so that `km * m`
+    /// becomes `km^2`; the returned factor accounts for that conversion.
+    pub fn mul(&self, rhs: &Unit) -> Result<(f64, Unit), EvalError> {
+        let mut factors = self.0.clone();
+        let mut factor = 1.;
+
+        for (base, exp) in &rhs.0 {
+            if let Some(entry) = factors.iter_mut().find(|(b, _)| b.name == base.name) {
+                entry.1 = entry.1.checked_add(*exp).ok_or(EvalError::Overflow)?;
+            } else if let Some(entry) = factors
+                .iter_mut()
+                .find(|(b, _)| b.dimension == base.dimension)
+            {
+                factor *= (base.scale / entry.0.scale).powi(*exp);
+                entry.1 = entry.1.checked_add(*exp).ok_or(EvalError::Overflow)?;
+            } else {
+                factors.push((base.clone(), *exp));
+            }
+        }
+
+        factors.retain(|(_, exp)| *exp != 0);
+        Ok((factor, Unit::checked(factors)?))
+    }
+
+    pub fn powi(&self, n: i32) -> Result<Unit, EvalError> {
+        let factors = self
+            .0
+            .iter()
+            .map(|(base, exp)| Some((base.clone(), exp.checked_mul(n)?)))
+            .filter(|factor| !matches!(factor, Some((_, 0))))
+            .collect::<Option<_>>()
+            .ok_or(EvalError::Overflow)?;
+        Unit::checked(factors)
+    }
+
+    /// A unit from its factors, unless the exponents of its dimension
+    /// overflow.
+    fn checked(factors: Vec<(Rc<BaseUnit>, i32)>) -> Result<Unit, EvalError> {
+        let unit = Unit(factors);
+        match unit.checked_dimension() {
+            Some(_) => Ok(unit),
+            None => Err(EvalError::Overflow),
+        }
+    }
+
+    /// The unit as an evar expression, such as `km * h^(-1)`.
+    pub fn to_source(&self) -> String {
+        self.0
+            .iter()
+            .map(|(base, exp)| match exp {
+                1 => base.name.clone(),
+                exp if *exp < 0 => format!("{}^({})", base.name, exp),
+                exp => format!("{}^{}", base.name, exp),
+            })
+            .collect::<Vec<_>>()
+            .join(" * ")
+    }
+
+    /// Name used in error messages, which also covers plain numbers.
+    pub fn describe(&self) -> String {
+        if self.0.is_empty() {
+            String::from("dimensionless")
+        } else {
+            self.to_string()
+        }
+    }
+}
+
+impl fmt::Display for Unit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let power = |name: &str, exp: i32| match exp {
+            1 => name.to_string(),
+            exp if exp < 0 => format!("{}^({})", name, exp),
+            exp => format!("{}^{}", name, exp),
+        };
+
+        let numerator = self
+            .0
+            .iter()
+            .filter(|(_, exp)| *exp > 0)
+            .map(|(base, exp)| power(&base.name, *exp))
+            .collect::<Vec<_>>();
+        let denominator = self
+            .0
+            .iter()
+            .filter(|(_, exp)| *exp < 0)
+            .map(|(base, exp)| power(&base.name, -exp))
+            .collect::<Vec<_>>();
+
+        match (numerator.is_empty(), denominator.len()) {
+            (_, 0) => write!(f, "{}", numerator.join(" ")),
+            (true, _) => {
+                let factors = self
+                    .0
+                    .iter()
+                    .map(|(base, exp)| power(&base.name, *exp))
+                    .collect::<Vec<_>>();
+                write!(f, "{}", factors.join(" "))
+            }
+            (false, 1) => write!(f, "{}/{}", numerator.join(" "), denominator[0]),
+            (false, _) => write!(f, "{}/({})", numerator.join(" "), denominator.join(" ")),
+        }
+    }
+}
@@ -1,6 +1,18 @@
-use super::EvalError;
+use super::{
+    Dimension, DisplayMode, EvalError, Unit,
+    format::{Digits, format_exponent, format_radix},
+};
+use num_bigint::BigInt;
+use num_complex::Complex64;
+use num_rational::BigRational;
+use num_traits::{FromPrimitive, One, Pow, Signed, ToPrimitive, Zero};
+use std::cmp::Ordering;
 use std::rc::Rc;
 
+/// Largest exact integer, in bits, that powers, factorials and shifts may produce.
+/// Anything larger is an overflow rather than a hang or out of memory.
+const MAX_INT_BITS: u64 = 1 << 18;
+
 #[derive(Debug, Clone, PartialEq)]
 pub struct Value(Rc<ValueInner>);
 
@@ -9,38 +21,306 @@ impl Value {
         Value(Rc::new(ValueInner::Null))
     }
 
-    pub fn print(&self, fix: Option<usize>) {
+    pub fn print(&self, fix: Option<usize>, mode: DisplayMode) {
+        if !matches!(&*self.0, ValueInner::Null) {
+            println!("{}", self.format(fix, mode));
+        }
+    }
+
+    /// Formats the value as it is printed, with `fix` decimal places if given.
+    /// Radix modes apply to integers only and the other modes to real numbers
+    /// and quantities; anything else is printed in decimal.
+    pub fn format(&self, fix: Option<usize>, mode: DisplayMode) -> String {
+        use ValueInner::*;
+        if let (Int(n), DisplayMode::Radix(radix, width)) = (&*self.0, mode) {
+            return format_radix(n, radix, width);
+        }
+        if !mode.has_exponent() {
+            return self.format_decimal(fix);
+        }
+
+        let real = |x: f64| x.is_finite().then(|| Digits::from_float(x));
+        let digits = match &*self.0 {
+            Int(n) => Some(Digits::from_int(n)),
+            Rational(r) => r.to_f64().and_then(real),
+            Float(x) | Quantity(x, _) => real(*x),
+            _ => None,
+        };
+        match (digits, &*self.0) {
+            (Some(digits), Quantity(_, unit)) => {
+                format!("{} {}", format_exponent(digits, mode, fix), unit)
+            }
+            (Some(digits), _) => format_exponent(digits, mode, fix),
+            (None, _) => self.format_decimal(fix),
+        }
+    }
+
+    fn format_decimal(&self, fix: Option<usize>) -> String {
         use ValueInner::*;
         match &*self.0 {
-            Int(n) => println!("{}", n),
+            Int(n) => n.to_string(),
+            Rational(r) => {
+                if let Some(fix) = fix {
+                    format_rational_fixed(r, fix)
+                } else {
+                    r.to_string()
+                }
+            }
             Float(f) => {
                 if let Some(fix) = fix {
-                    println!("{:.*}", fix, f);
+                    format!("{:.*}", fix, f)
                 } else {
-                    println!("{}", f);
+                    f.to_string()
                 }
             }
-            Null => {}
+            Complex(z) => format_complex(z, fix),
+            Bool(b) => b.to_string(),
+            Quantity(x, unit) => {
+                if let Some(fix) = fix {
+                    format!("{:.*} {}", fix, x, unit)
+                } else {
+                    format!("{} {}", x, unit)
+                }
+            }
+            Null => String::new(),
+        }
+    }
+
+    pub fn type_name(&self) -> String {
+        self.0.type_name()
+    }
+
+    /// Writes the value as an evar expression that evaluates to it, or
+    /// `None` for values without one, such as null and NaN.
+    pub fn to_source(&self) -> Option<String> {
+        use ValueInner::*;
+        let float = |x: f64| match x {
+            x if x.is_nan() => None,
+            x if x.is_infinite() => Some(String::from(if x < 0. { "-1e999" } else { "1e999" })),
+            x => Some(format!("{:?}", x)),
+        };
+        match &*self.0 {
+            Null => None,
+            Int(n) => Some(n.to_string()),
+            Rational(r) => Some(r.to_string()),
+            Float(x) => float(*x),
+            Complex(z) => Some(format!("{} + {} * i", float(z.re)?, float(z.im)?)),
+            Quantity(x, unit) => Some(format!("{} * {}", float(*x)?, unit.to_source())),
+            Bool(b) => Some(b.to_string()),
+        }
+    }
+
+    pub fn is_null(&self) -> bool {
+        matches!(&*self.0, ValueInner::Null)
+    }
+
+    pub fn to_bool(&self) -> Result<bool, EvalError> {
+        match &*self.0 {
+            ValueInner::Bool(b) => Ok(*b),
+            v => Err(EvalError::TypeError(String::from("Boolean"), v.type_name())),
+        }
+    }
+
+    pub fn is_complex(&self) -> bool {
+        matches!(&*self.0, ValueInner::Complex(_))
+    }
+
+    /// Creates a quantity, collapsing it into a plain number when the units
+    /// cancel out.
+    pub fn quantity(magnitude: f64, unit: Unit) -> Value {
+        if unit.dimension().is_dimensionless() {
+            (magnitude * unit.scale()).into()
+        } else {
+            Value(Rc::new(ValueInner::Quantity(magnitude, unit)))
+        }
+    }
+
+    fn is_quantity(&self) -> bool {
+        matches!(&*self.0, ValueInner::Quantity(..))
+    }
+
+    /// Splits the value into a magnitude and a unit, treating plain numbers
+    /// as dimensionless.
+    fn to_quantity(&self) -> Result<(f64, Unit), EvalError> {
+        match &*self.0 {
+            ValueInner::Quantity(x, unit) => Ok((*x, unit.clone())),
+            _ => Ok((self.to_float()?, Unit::default())),
+        }
+    }
+
+    /// The size of the value in radians, if it is an angle quantity.
+    pub fn angle_in_radians(&self) -> Option<f64> {
+        match &*self.0 {
+            ValueInner::Quantity(x, unit) if unit.dimension() == Dimension::ANGLE => {
+                Some(x * unit.scale())
+            }
+            _ => None,
+        }
+    }
+
+    /// Both operands as plain numbers if one is an angle and the other a
+    /// plain number. Angles are then taken in radians.
+    fn angle_and_number(&self, rhs: &Value) -> Result<Option<(f64, f64)>, EvalError> {
+        match (self.angle_in_radians(), rhs.angle_in_radians()) {
+            (Some(x), None) if !rhs.is_quantity() => Ok(Some((x, rhs.to_float()?))),
+            (None, Some(y)) if !self.is_quantity() => Ok(Some((self.to_float()?, y))),
+            _ => Ok(None),
         }
     }
 
+    /// Expresses the value in the unit of `target`.
+    pub fn convert(self, target: Value) -> Result<Value, EvalError> {
+        let ValueInner::Quantity(_, target_unit) = &*target.0 else {
+            return Err(EvalError::TypeError(
+                String::from("Quantity"),
+                target.0.type_name(),
+            ));
+        };
+        let (x, unit) = self.to_quantity()?;
+        if unit.dimension() != target_unit.dimension() {
+            return Err(EvalError::IncompatibleUnits(
+                unit.describe(),
+                target_unit.describe(),
+            ));
+        }
+        Ok(Value::quantity(
+            x * unit.scale() / target_unit.scale(),
+            target_unit.clone(),
+        ))
+    }
+
     pub fn to_float(&self) -> Result<f64, EvalError> {
         match &*self.0.clone() {
-            ValueInner::Int(x) => Ok(f64::from(*x)),
+            ValueInner::Int(x) => Ok(int_to_float(x)),
+            ValueInner::Rational(x) => Ok(x.to_f64().unwrap_or(f64::NAN)),
             ValueInner::Float(x) => Ok(*x),
+            ValueInner::Quantity(x, unit) if unit.dimension() == Dimension::ANGLE => {
+                Ok(x * unit.scale())
+            }
             v => Err(EvalError::InvalidConversion(
                 v.type_name(),
                 String::from("Float"),
             )),
         }
     }
+
+    pub fn to_complex(&self) -> Result<Complex64, EvalError> {
+        match &*self.0 {
+            ValueInner::Complex(z) => Ok(*z),
+            _ => Ok(Complex64::from(self.to_float()?)),
+        }
+    }
+
+    /// Converts the value into an exact fraction. Floats are approximated by
+    /// the simplest fraction that rounds back to the same float.
+    pub fn to_rational(&self) -> Result<BigRational, EvalError> {
+        match &*self.0 {
+            ValueInner::Int(x) => Ok(BigRational::from(x.clone())),
+            ValueInner::Rational(x) => Ok(x.clone()),
+            ValueInner::Float(x) => float_to_rational(*x).ok_or(EvalError::InvalidConversion(
+                String::from("Float"),
+                String::from("Rational"),
+            )),
+            ValueInner::Quantity(..) if self.angle_in_radians().is_some() => {
+                Value::from(self.to_float()?).to_rational()
+            }
+            v => Err(EvalError::InvalidConversion(
+                v.type_name(),
+                String::from("Rational"),
+            )),
+        }
+    }
+
+    /// Converts a float into an integer value, failing on NaN and infinities.
+    pub fn from_float_to_int(x: f64) -> Result<Value, EvalError> {
+        BigInt::from_f64(x)
+            .map(Value::from)
+            .ok_or(EvalError::InvalidConversion(
+                String::from("Float"),
+                String::from("Integer"),
+            ))
+    }
+}
+
+fn shift_amount(n: &BigInt) -> Result<u32, EvalError> {
+    if n.is_negative() {
+        return Err(EvalError::MathDomain(
+            "the shift amount must be a non-negative integer".to_string(),
+        ));
+    }
+    n.to_u32().ok_or(EvalError::Overflow)
+}
+
+fn int_to_float(x: &BigInt) -> f64 {
+    x.to_f64().unwrap_or(f64::NAN)
+}
+
+/// `x` raised to the power `y`, complex if `promote` is set and the result
+/// is not real.
+fn real_pow(x: f64, y: f64, promote: bool) -> Value {
+    if promote && x < 0. && y.fract() != 0. {
+        Complex64::from(x).powf(y).into()
+    } else {
+        x.powf(y).into()
+    }
+}
+
+fn float_to_rational(x: f64) -> Option<BigRational> {
+    // walk the convergents of the continued fraction of x
+    let mut rest = BigRational::from_float(x)?;
+    let (mut h0, mut h1) = (BigInt::zero(), BigInt::one());
+    let (mut k0, mut k1) = (BigInt::one(), BigInt::zero());
+    loop {
+        let a = rest.floor().to_integer();
+        let h2 = &a * &h1 + &h0;
+        let k2 = &a * &k1 + &k0;
+        let approx = BigRational::new(h2.clone(), k2.clone());
+        let frac = rest - BigRational::from(a);
+        if frac.is_zero() || approx.to_f64() == Some(x) {
+            return Some(approx);
+        }
+        rest = frac.recip();
+        (h0, h1) = (h1, h2);
+        (k0, k1) = (k1, k2);
+    }
+}
+
+fn format_rational_fixed(r: &BigRational, fix: usize) -> String {
+    let scale = BigRational::from(BigInt::from(10).pow(fix as u32));
+    let scaled = (r * scale).round().to_integer();
+    let digits = format!("{:0>width$}", scaled.abs(), width = fix + 1);
+    let (int_part, frac_part) = digits.split_at(digits.len() - fix);
+    let sign = if scaled.is_negative() { "-" } else { "" };
+    if fix == 0 {
+        format!("{}{}", sign, int_part)
+    } else {
+        format!("{}{}.{}", sign, int_part, frac_part)
+    }
+}
+
+fn format_complex(z: &Complex64, fix: Option<usize>) -> String {
+    let format_part = |x: f64| match fix {
+        Some(fix) => format!("{:.*}", fix, x),
+        None => format!("{}", x),
+    };
+    let im = format!("{}i", format_part(z.im.abs()));
+    match (z.re == 0.0, z.im.is_sign_negative()) {
+        (true, false) => im,
+        (true, true) => format!("-{}", im),
+        (false, false) => format!("{}+{}", format_part(z.re), im),
+        (false, true) => format!("{}-{}", format_part(z.re), im),
+    }
 }
 
 #[derive(Debug, PartialEq)]
 enum ValueInner {
     Null,
-    Int(i32),
+    Int(BigInt),
+    Rational(BigRational),
     Float(f64),
+    Complex(Complex64),
+    Quantity(f64, Unit),
+    Bool(bool),
 }
 
 impl ValueInner {
@@ -49,14 +329,61 @@ impl ValueInner {
         match self {
             Null => String::from("Null"),
             Int(_) => String::from("Integer"),
+            Rational(_) => String::from("Rational"),
             Float(_) => String::from("Float"),
+            Complex(_) => String::from("Complex"),
+            Quantity(..) => String::from("Quantity"),
+            Bool(_) => String::from("Boolean"),
         }
     }
+
+    fn is_number(&self) -> bool {
+        use ValueInner::*;
+        matches!(self, Int(_) | Rational(_) | Float(_) | Complex(_))
+    }
+}
+
+impl std::fmt::Display for Value {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.format_decimal(None))
+    }
+}
+
+impl From<BigInt> for Value {
+    fn from(value: BigInt) -> Self {
+        Value(Rc::new(ValueInner::Int(value)))
+    }
+}
+
+impl From<BigRational> for Value {
+    fn from(value: BigRational) -> Self {
+        if value.is_integer() {
+            value.to_integer().into()
+        } else {
+            Value(Rc::new(ValueInner::Rational(value)))
+        }
+    }
+}
+
+impl From<Complex64> for Value {
+    fn from(value: Complex64) -> Self {
+        if value.im == 0.0 {
+            value.re.into()
+        } else {
+            Value(Rc::new(ValueInner::Complex(value)))
+        }
+    }
+}
+
+impl From<bool> for Value {
+    fn from(value: bool) -> Self {
+        Value(Rc::new(ValueInner::Bool(value)))
+    }
 }
 
 impl From<i32> for Value {
     fn from(value: i32) -> Self {
-        Value(Rc::new(ValueInner::Int(value)))
+        BigInt::from(value).into()
     }
 }
 
@@ -66,140 +393,276 @@ impl From<f64> for Value {
     }
 }
 
+/// Operands of a binary operation, promoted to their common numeric type.
+enum Operands {
+    Int(BigInt, BigInt),
+    Rational(BigRational, BigRational),
+    Float(f64, f64),
+    Complex(Complex64, Complex64),
+}
+
+impl Value {
+    fn coerce(&self, rhs: &Value) -> Result<Operands, EvalError> {
+        use ValueInner::*;
+        if let Some(x) = self.angle_in_radians() {
+            return Value::from(x).coerce(rhs);
+        }
+        if let Some(y) = rhs.angle_in_radians() {
+            return self.coerce(&Value::from(y));
+        }
+        match (&*self.0, &*rhs.0) {
+            (Int(x), Int(y)) => Ok(Operands::Int(x.clone(), y.clone())),
+            (Int(_) | Rational(_), Int(_) | Rational(_)) => {
+                Ok(Operands::Rational(self.to_rational()?, rhs.to_rational()?))
+            }
+            (Int(_) | Rational(_) | Float(_), Int(_) | Rational(_) | Float(_)) => {
+                Ok(Operands::Float(self.to_float()?, rhs.to_float()?))
+            }
+            (
+                Int(_) | Rational(_) | Float(_) | Complex(_),
+                Int(_) | Rational(_) | Float(_) | Complex(_),
+            ) => Ok(Operands::Complex(self.to_complex()?, rhs.to_complex()?)),
+            (x, y) => {
+                let v = if x.is_number() { y } else { x };
+                Err(EvalError::TypeError(String::from("Number"), v.type_name()))
+            }
+        }
+    }
+}
+
+impl Value {
+    pub fn equals(&self, rhs: &Value) -> Result<bool, EvalError> {
+        use ValueInner::*;
+        match (&*self.0, &*rhs.0) {
+            (Bool(x), Bool(y)) => Ok(x == y),
+            (Bool(_), v) | (v, Bool(_)) => {
+                Err(EvalError::TypeError(String::from("Boolean"), v.type_name()))
+            }
+            (Complex(_), _) | (_, Complex(_)) => match self.coerce(rhs)? {
+                Operands::Complex(x, y) => Ok(x == y),
+                _ => unreachable!(),
+            },
+            _ => Ok(self.compare(rhs)? == Some(Ordering::Equal)),
+        }
+    }
+
+    /// Orders two real values, converting quantities to a common unit.
+    pub fn compare(&self, rhs: &Value) -> Result<Option<Ordering>, EvalError> {
+        if let Some((x, y)) = self.angle_and_number(rhs)? {
+            return Ok(x.partial_cmp(&y));
+        }
+        if self.is_quantity() || rhs.is_quantity() {
+            let (x, x_unit) = self.to_quantity()?;
+            let (y, y_unit) = rhs.to_quantity()?;
+            if x_unit.dimension() != y_unit.dimension() {
+                return Err(EvalError::IncompatibleUnits(
+                    x_unit.describe(),
+                    y_unit.describe(),
+                ));
+            }
+            return Ok(x.partial_cmp(&(y * y_unit.scale() / x_unit.scale())));
+        }
+        match self.coerce(rhs)? {
+            Operands::Int(x, y) => Ok(Some(x.cmp(&y))),
+            Operands::Rational(x, y) => Ok(Some(x.cmp(&y))),
+            Operands::Float(x, y) => Ok(x.partial_cmp(&y)),
+            Operands::Complex(..) => Err(EvalError::TypeError(
+                String::from("Real"),
+                String::from("Complex"),
+            )),
+        }
+    }
+
+    fn add_quantities(self, rhs: Value, sign: f64) -> Result<Value, EvalError> {
+        if let Some((x, y)) = self.angle_and_number(&rhs)? {
+            return Ok((x + sign * y).into());
+        }
+        let (x, x_unit) = self.to_quantity()?;
+        let (y, y_unit) = rhs.to_quantity()?;
+        if x_unit.dimension() != y_unit.dimension() {
+            return Err(EvalError::IncompatibleUnits(
+                x_unit.describe(),
+                y_unit.describe(),
+            ));
+        }
+        let y = y * y_unit.scale() / x_unit.scale();
+        Ok(Value::quantity(x + sign * y, x_unit))
+    }
+
+    fn mul_quantities(self, rhs: Value) -> Result<Value, EvalError> {
+        let (x, x_unit) = self.to_quantity()?;
+        let (y, y_unit) = rhs.to_quantity()?;
+        let (factor, unit) = x_unit.mul(&y_unit)?;
+        Ok(Value::quantity(x * y * factor, unit))
+    }
+
+    fn div_quantities(self, rhs: Value) -> Result<Value, EvalError> {
+        let (x, x_unit) = self.to_quantity()?;
+        let (y, y_unit) = rhs.to_quantity()?;
+        if y == 0.0 {
+            return Err(EvalError::DivisionByZero);
+        }
+        let (factor, unit) = x_unit.mul(&y_unit.powi(-1)?)?;
+        Ok(Value::quantity(x / y * factor, unit))
+    }
+}
+
 macro_rules! define_binop {
-    ($trait:ident, $fname:ident) => {
+    ($trait:ident, $fname:ident, $quantity_op:expr) => {
         impl $trait<Value> for Value {
-            type Output = Value;
+            type Output = Result<Value, EvalError>;
             fn $fname(self, rhs: Value) -> Self::Output {
-                use ValueInner::*;
-                match (&*self.0, &*rhs.0) {
-                    (Int(x), Int(y)) => x.$fname(y).into(),
-                    (Float(x), Int(y)) => x.$fname(f64::from(*y)).into(),
-                    (Int(x), Float(y)) => f64::from(*x).$fname(y).into(),
-                    (Float(x), Float(y)) => x.$fname(y).into(),
-                    _ => unimplemented!(),
+                if self.is_quantity() || rhs.is_quantity() {
+                    return $quantity_op(self, rhs);
                 }
+                Ok(match self.coerce(&rhs)? {
+                    Operands::Int(x, y) => x.$fname(y).into(),
+                    Operands::Rational(x, y) => x.$fname(y).into(),
+                    Operands::Float(x, y) => x.$fname(y).into(),
+                    Operands::Complex(x, y) => x.$fname(y).into(),
+                })
             }
         }
     };
 }
 
 use std::ops::{Add, Div, Mul, Neg, Sub};
-define_binop!(Add, add);
-define_binop!(Sub, sub);
-define_binop!(Mul, mul);
+define_binop!(Add, add, |x: Value, y| x.add_quantities(y, 1.));
+define_binop!(Sub, sub, |x: Value, y| x.add_quantities(y, -1.));
+define_binop!(Mul, mul, Value::mul_quantities);
 
 impl Div<Value> for Value {
     type Output = Result<Value, EvalError>;
     fn div(self, rhs: Value) -> Self::Output {
-        use ValueInner::*;
-        match (&*self.0, &*rhs.0) {
-            (Int(x), Int(y)) => {
-                if *y == 0 {
+        if self.is_quantity() || rhs.is_quantity() {
+            return self.div_quantities(rhs);
+        }
+        match self.coerce(&rhs)? {
+            Operands::Int(x, y) => {
+                if y.is_zero() {
                     return Err(EvalError::DivisionByZero);
                 }
-                Ok(f64::from(*x).div(f64::from(*y)).into())
+                Ok(BigRational::new(x, y).into())
             }
-            (Float(x), Int(y)) => {
-                if *y == 0 {
+            Operands::Rational(x, y) => {
+                if y.is_zero() {
                     return Err(EvalError::DivisionByZero);
                 }
-                Ok(x.div(f64::from(*y)).into())
+                Ok(x.div(y).into())
             }
-            (Int(x), Float(y)) => {
-                if *y == 0.0 {
+            Operands::Float(x, y) => {
+                if y == 0.0 {
                     return Err(EvalError::DivisionByZero);
                 }
-                Ok(f64::from(*x).div(y).into())
+                Ok(x.div(y).into())
             }
-            (Float(x), Float(y)) => {
-                if *y == 0.0 {
+            Operands::Complex(x, y) => {
+                if y.is_zero() {
                     return Err(EvalError::DivisionByZero);
                 }
                 Ok(x.div(y).into())
             }
-            _ => unimplemented!(),
         }
     }
 }
 
 impl Value {
     pub fn rem_euclid(self, rhs: Value) -> Result<Value, EvalError> {
-        use ValueInner::*;
-        match (&*self.0, &*rhs.0) {
-            (Int(x), Int(y)) => {
-                if *y == 0 {
+        match self.coerce(&rhs)? {
+            Operands::Int(x, y) => {
+                if y.is_zero() {
                     return Err(EvalError::DivisionByZero);
                 }
-                Ok(x.rem_euclid(*y).into())
+                Ok(num_traits::Euclid::rem_euclid(&x, &y).into())
             }
-            (Float(x), Int(y)) => {
-                if *y == 0 {
+            Operands::Rational(x, y) => {
+                if y.is_zero() {
                     return Err(EvalError::DivisionByZero);
                 }
-                Ok(x.rem_euclid(f64::from(*y)).into())
+                let y = y.abs();
+                let q = (&x / &y).floor();
+                Ok((x - y * q).into())
             }
-            (Int(x), Float(y)) => {
-                if *y == 0.0 {
+            Operands::Float(x, y) => {
+                if y == 0.0 {
                     return Err(EvalError::DivisionByZero);
                 }
-                Ok(f64::from(*x).rem_euclid(*y).into())
+                Ok(x.rem_euclid(y).into())
             }
-            (Float(x), Float(y)) => {
-                if *y == 0.0 {
-                    return Err(EvalError::DivisionByZero);
-                }
-                Ok(x.rem_euclid(*y).into())
-            }
-            _ => unimplemented!(),
+            Operands::Complex(..) => Err(EvalError::TypeError(
+                String::from("Real"),
+                String::from("Complex"),
+            )),
         }
     }
 
     pub fn int_div(self, rhs: Value) -> Result<Value, EvalError> {
-        use ValueInner::*;
-        match (&*self.0, &*rhs.0) {
-            (Int(x), Int(y)) => {
-                if *y == 0 {
-                    return Err(EvalError::DivisionByZero);
-                }
-                Ok(x.div(*y).into())
-            }
-            (Float(x), Int(y)) => {
-                if *y == 0 {
+        match self.coerce(&rhs)? {
+            Operands::Int(x, y) => {
+                if y.is_zero() {
                     return Err(EvalError::DivisionByZero);
                 }
-                Ok((x.div(f64::from(*y)).floor() as i32).into())
+                Ok(x.div(y).into())
             }
-            (Int(x), Float(y)) => {
-                if *y == 0.0 {
+            Operands::Rational(x, y) => {
+                if y.is_zero() {
                     return Err(EvalError::DivisionByZero);
                 }
-                Ok((f64::from(*x).div(*y).floor() as i32).into())
+                Ok(x.div(y).floor().to_integer().into())
             }
-            (Float(x), Float(y)) => {
-                if *y == 0.0 {
+            Operands::Float(x, y) => {
+                if y == 0.0 {
                     return Err(EvalError::DivisionByZero);
                 }
-                Ok((x.div(*y).floor() as i32).into())
+                Value::from_float_to_int(x.div(y).floor())
             }
-            _ => unimplemented!(),
+            Operands::Complex(..) => Err(EvalError::TypeError(
+                String::from("Real"),
+                String::from("Complex"),
+            )),
         }
     }
 
-    pub fn pow(self, rhs: Value) -> Self {
+    /// Raises the value to the power `rhs`. A negative real base with a
+    /// fractional exponent gives a complex result if `promote` is set.
+    pub fn pow(self, rhs: Value, promote: bool) -> Result<Value, EvalError> {
         use ValueInner::*;
         match (&*self.0, &*rhs.0) {
-            (Int(x), Int(y)) => {
-                if *y < 0 {
-                    f64::from(*x).powi(*y).into()
+            (Int(_) | Rational(_), Int(y)) => {
+                let base = self.to_rational()?;
+                let exponent = y.abs().to_u32().ok_or(EvalError::Overflow)?;
+                let bits = base.numer().bits().max(base.denom().bits());
+                if bits > 1 && (bits - 1).saturating_mul(exponent.into()) > MAX_INT_BITS {
+                    return Err(EvalError::Overflow);
+                }
+                if y.is_negative() {
+                    if base.is_zero() {
+                        return Err(EvalError::DivisionByZero);
+                    }
+                    Ok(base.recip().pow(exponent).into())
                 } else {
-                    (*x).pow(*y as u32).into()
+                    Ok(base.pow(exponent).into())
                 }
             }
-            (Float(x), Int(y)) => x.powi(*y).into(),
-            (Int(x), Float(y)) => f64::from(*x).powf(*y).into(),
-            (Float(x), Float(y)) => x.powf(*y).into(),
-            _ => unimplemented!(),
+            (Int(_) | Rational(_), Rational(_)) => {
+                Ok(real_pow(self.to_float()?, rhs.to_float()?, promote))
+            }
+            (Quantity(x, unit), Int(y)) => {
+                let y = y.to_i32().ok_or(EvalError::Overflow)?;
+                Ok(Value::quantity(x.powi(y), unit.powi(y)?))
+            }
+            (Quantity(..), v) if self.angle_in_radians().is_none() => {
+                Err(EvalError::TypeError(String::from("Integer"), v.type_name()))
+            }
+            (Complex(z), Int(y)) => match y.to_i32() {
+                Some(y) => Ok(z.powi(y).into()),
+                None => Ok(z.powf(int_to_float(y)).into()),
+            },
+            _ => match self.coerce(&rhs)? {
+                Operands::Float(x, y) => Ok(real_pow(x, y, promote)),
+                Operands::Complex(x, y) => Ok(x.powc(y).into()),
+                Operands::Int(..) | Operands::Rational(..) => unreachable!(),
+            },
         }
     }
 
@@ -207,40 +670,158 @@ impl Value {
         use ValueInner::*;
         match &*self.0 {
             Int(n) => {
-                let n = *n;
-                if n < 0 {
+                if n.is_negative() {
                     return Err(EvalError::MathDomain(
                         "the argument must be a non-negative integer".to_string(),
                     ));
                 }
-                let result = (1..=n).try_fold(1_i32, |acc, x| acc.checked_mul(x));
-                match result {
-                    Some(n) => Ok(n.into()),
-                    None => Err(EvalError::Overflow),
-                }
+                let n = n.to_u32().ok_or(EvalError::Overflow)?;
+                let factorial = (1..=n).try_fold(BigInt::one(), |acc, x| {
+                    let acc = acc * x;
+                    (acc.bits() <= MAX_INT_BITS)
+                        .then_some(acc)
+                        .ok_or(EvalError::Overflow)
+                })?;
+                Ok(factorial.into())
             }
             v => Err(EvalError::TypeError(String::from("Integer"), v.type_name())),
         }
     }
 
-    pub fn abs(&self) -> Value {
+    /// Both operands of a bitwise operator, which must be integers.
+    fn integers<'a>(&'a self, rhs: &'a Value) -> Result<(&'a BigInt, &'a BigInt), EvalError> {
+        use ValueInner::*;
+        match (&*self.0, &*rhs.0) {
+            (Int(x), Int(y)) => Ok((x, y)),
+            (Int(_), v) | (v, _) => {
+                Err(EvalError::TypeError(String::from("Integer"), v.type_name()))
+            }
+        }
+    }
+
+    pub fn bit_and(self, rhs: Value) -> Result<Value, EvalError> {
+        let (x, y) = self.integers(&rhs)?;
+        Ok((x & y).into())
+    }
+
+    pub fn bit_or(self, rhs: Value) -> Result<Value, EvalError> {
+        let (x, y) = self.integers(&rhs)?;
+        Ok((x | y).into())
+    }
+
+    pub fn bit_xor(self, rhs: Value) -> Result<Value, EvalError> {
+        let (x, y) = self.integers(&rhs)?;
+        Ok((x ^ y).into())
+    }
+
+    /// Bitwise complement, `-x - 1` in two's complement.
+    pub fn bit_not(&self) -> Result<Value, EvalError> {
+        match &*self.0 {
+            ValueInner::Int(n) => Ok((!n).into()),
+            v => Err(EvalError::TypeError(String::from("Integer"), v.type_name())),
+        }
+    }
+
+    pub fn shift_left(self, rhs: Value) -> Result<Value, EvalError> {
+        let (x, y) = self.integers(&rhs)?;
+        let amount = shift_amount(y)?;
+        if !x.is_zero() && x.bits() + u64::from(amount) > MAX_INT_BITS {
+            return Err(EvalError::Overflow);
+        }
+        Ok((x << amount).into())
+    }
+
+    /// Arithmetic right shift, rounding towards negative infinity.
+    pub fn shift_right(self, rhs: Value) -> Result<Value, EvalError> {
+        let (x, y) = self.integers(&rhs)?;
+        Ok((x >> shift_amount(y)?).into())
+    }
+
+    pub fn abs(&self) -> Result<Value, EvalError> {
+        use ValueInner::*;
+        match &*self.0 {
+            Int(n) => Ok(n.abs().into()),
+            Rational(n) => Ok(n.abs().into()),
+            Float(n) => Ok(n.abs().into()),
+            Complex(z) => Ok(z.norm().into()),
+            Quantity(x, unit) => Ok(Value::quantity(x.abs(), unit.clone())),
+            v => Err(EvalError::TypeError(String::from("Number"), v.type_name())),
+        }
+    }
+
+    pub fn re(&self) -> Result<Value, EvalError> {
         use ValueInner::*;
         match &*self.0 {
-            Int(n) => n.abs().into(),
-            Float(n) => n.abs().into(),
-            _ => unimplemented!(),
+            Int(_) | Rational(_) | Float(_) => Ok(self.clone()),
+            Complex(z) => Ok(z.re.into()),
+            v => Err(EvalError::TypeError(String::from("Number"), v.type_name())),
+        }
+    }
+
+    pub fn im(&self) -> Result<Value, EvalError> {
+        use ValueInner::*;
+        match &*self.0 {
+            Int(_) | Rational(_) | Float(_) => Ok(0.into()),
+            Complex(z) => Ok(z.im.into()),
+            v => Err(EvalError::TypeError(String::from("Number"), v.type_name())),
+        }
+    }
+
+    pub fn arg(&self) -> Result<Value, EvalError> {
+        Ok(self.to_complex()?.arg().into())
+    }
+
+    pub fn conj(&self) -> Result<Value, EvalError> {
+        use ValueInner::*;
+        match &*self.0 {
+            Int(_) | Rational(_) | Float(_) => Ok(self.clone()),
+            Complex(z) => Ok(z.conj().into()),
+            v => Err(EvalError::TypeError(String::from("Number"), v.type_name())),
+        }
+    }
+
+    pub fn floor(&self) -> Result<Value, EvalError> {
+        use ValueInner::*;
+        match &*self.0 {
+            Int(_) => Ok(self.clone()),
+            Rational(n) => Ok(n.floor().to_integer().into()),
+            Float(n) => Value::from_float_to_int(n.floor()),
+            v => Err(EvalError::TypeError(String::from("Number"), v.type_name())),
+        }
+    }
+
+    pub fn ceil(&self) -> Result<Value, EvalError> {
+        use ValueInner::*;
+        match &*self.0 {
+            Int(_) => Ok(self.clone()),
+            Rational(n) => Ok(n.ceil().to_integer().into()),
+            Float(n) => Value::from_float_to_int(n.ceil()),
+            v => Err(EvalError::TypeError(String::from("Number"), v.type_name())),
+        }
+    }
+
+    pub fn round(&self) -> Result<Value, EvalError> {
+        use ValueInner::*;
+        match &*self.0 {
+            Int(_) => Ok(self.clone()),
+            Rational(n) => Ok(n.round().to_integer().into()),
+            Float(n) => Value::from_float_to_int(n.round()),
+            v => Err(EvalError::TypeError(String::from("Number"), v.type_name())),
         }
     }
 }
 
 impl Neg for Value {
-    type Output = Value;
+    type Output = Result<Value, EvalError>;
     fn neg(self) -> Self::Output {
         use ValueInner::*;
         match &*self.0 {
-            Int(x) => x.neg().into(),
-            Float(x) => x.neg().into(),
-            _ => unimplemented!(),
+            Int(x) => Ok(x.neg().into()),
+            Rational(x) => Ok(x.neg().into()),
+            Float(x) => Ok(x.neg().into()),
+            Complex(x) => Ok(x.neg().into()),
+            Quantity(x, unit) => Ok(Value::quantity(x.neg(), unit.clone())),
+            v => Err(EvalError::TypeError(String::from("Number"), v.type_name())),
         }
     }
 }
@@ -1,21 +1,42 @@
 #[cfg(test)]
 mod test;
 
-use crate::models::{Expr, Stmt, Token, operators::*};
+use crate::models::{DisplayMode, Expr, Stmt, Token, operators::*};
 
 use chumsky::input::ValueInput;
 use chumsky::prelude::*;
+use num_traits::ToPrimitive;
 
-pub fn parser<'a, I>() -> impl Parser<'a, I, Stmt, extra::Err<Rich<'a, Token<'a>>>>
+/// Parses one statement. `source` is the text the tokens were lexed from,
+/// used to keep function bodies as written.
+pub fn parser<'a, I>(source: &'a str) -> impl Parser<'a, I, Stmt, extra::Err<Rich<'a, Token<'a>>>>
 where
     I: ValueInput<'a, Token = Token<'a>, Span = SimpleSpan>,
 {
-    function_definition()
-        .or(variable_definition())
+    let display_mode = select! {
+        Token::Ident(name) => name
+    }
+    .labelled("display mode")
+    .try_map(|name, span| {
+        name.parse::<DisplayMode>()
+            .map_err(|err| Rich::custom(span, err))
+    });
+
+    function_definition(source).or(variable_definition()
         .or(expression().map(Stmt::Expr))
+        .then(just(Token::As).ignore_then(display_mode).or_not())
+        .map(|(stmt, mode)| match mode {
+            Some(mode) => Stmt::Formatted {
+                stmt: Box::new(stmt),
+                mode,
+            },
+            None => stmt,
+        }))
 }
 
-pub fn function_definition<'a, I>() -> impl Parser<'a, I, Stmt, extra::Err<Rich<'a, Token<'a>>>>
+pub fn function_definition<'a, I>(
+    source: &'a str,
+) -> impl Parser<'a, I, Stmt, extra::Err<Rich<'a, Token<'a>>>>
 where
     I: ValueInput<'a, Token = Token<'a>, Span = SimpleSpan>,
 {
@@ -25,18 +46,43 @@ where
     .boxed()
     .labelled("ident");
 
+    let arg_names = ident
+        .clone()
+        .separated_by(just(Token::Comma))
+        .collect()
+        .delimited_by(just(Token::LParen), just(Token::RParen))
+        .recover_with(via_parser(nested_delimiters(
+            Token::LParen,
+            Token::RParen,
+            [],
+            |_| Vec::new(),
+        )));
+
     just(Token::Let)
-        .ignore_then(ident.clone())
-        .then_ignore(just(Token::LParen))
-        .then(ident.separated_by(just(Token::Comma)).collect())
-        .then_ignore(just(Token::RParen))
+        .ignore_then(ident.recover_with(via_parser(skip_name([Token::LParen]))))
+        .then(arg_names)
         .then_ignore(just(Token::Equal))
-        .then(expression())
-        .map(|((name, arg_names), body)| Stmt::DefFun {
-            name,
-            arg_names,
-            body,
-        })
+        .then(expression().map_with(|body, e| (body, e.span())))
+        .map(
+            move |((name, arg_names), (mut body, span)): (_, (Expr, SimpleSpan))| {
+                // comments are not tokens the parser sees, so whatever follows
+                // the body is the docstring
+                let doc = source[span.end..]
+                    .trim()
+                    .strip_prefix('#')
+                    .map(|doc| doc.trim().to_string())
+                    .filter(|doc| !doc.is_empty());
+                // errors in the body point into its source
+                body.rebase(span.start);
+                Stmt::DefFun {
+                    name,
+                    arg_names,
+                    body,
+                    source: source[span.into_range()].to_string(),
+                    doc,
+                }
+            },
+        )
         .labelled("function definition")
         .as_context()
 }
@@ -52,7 +98,7 @@ where
     .labelled("ident");
 
     just(Token::Let)
-        .ignore_then(ident)
+        .ignore_then(ident.recover_with(via_parser(skip_name([]))))
         .then_ignore(just(Token::Equal))
         .then(expression())
         .map(|(name, expr)| Stmt::DefVar { name, expr })
@@ -64,10 +110,29 @@ pub fn expression<'a, I>() -> impl Parser<'a, I, Expr, extra::Err<Rich<'a, Token
 where
     I: ValueInput<'a, Token = Token<'a>, Span = SimpleSpan>,
 {
+    // `2 rad(x)` calls the function rather than writing an angle
+    let angle_unit = select! {
+        Token::Ident(unit @ ("deg" | "rad")) => unit.to_string()
+    }
+    .then_ignore(just(Token::LParen).not());
+
     let number = select! {
         Token::Int(n) => Expr::Int(n),
-        Token::Float(n) => Expr::Float(n)
+        Token::Float(n) => Expr::Float(n),
     }
+    .then(angle_unit.or_not())
+    .map(|(number, unit)| match (number, unit) {
+        (Expr::Int(n), Some(unit)) => Expr::Quantity {
+            magnitude: n.to_f64().unwrap_or(f64::NAN),
+            unit,
+        },
+        (Expr::Float(magnitude), Some(unit)) => Expr::Quantity { magnitude, unit },
+        (number, _) => number,
+    })
+    .or(select! {
+        Token::Degrees(n) => Expr::Quantity { magnitude: n, unit: String::from("deg") },
+    })
+    .map_with(|expr, e| spanned(expr, e.span()))
     .labelled("number")
     .boxed();
 
@@ -76,79 +141,103 @@ where
             Token::Ident(ident) => ident.to_string()
         }
         .labelled("ident")
-        .then_ignore(just(Token::LParen))
-        .then(expr.clone().separated_by(just(Token::Comma)).collect())
-        .then_ignore(just(Token::RParen))
-        .map(|(name, args)| Expr::FnCall { name, args })
+        .then(
+            expr.clone()
+                .separated_by(just(Token::Comma))
+                .collect()
+                .delimited_by(just(Token::LParen), just(Token::RParen))
+                .recover_with(via_parser(nested_delimiters(
+                    Token::LParen,
+                    Token::RParen,
+                    [],
+                    |_| Vec::new(),
+                ))),
+        )
+        .map_with(|(name, args), e| spanned(Expr::FnCall { name, args }, e.span()))
         .boxed();
 
         let variable = select! {
             Token::Ident(ident) => Expr::Variable(ident.to_string())
         }
+        .map_with(|expr, e| spanned(expr, e.span()))
         .labelled("ident");
 
         let atomic = choice((
             number.clone(),
             fn_call,
             variable,
-            just(Token::Underscore).map(|_| Expr::PrevAnswer),
+            just(Token::Underscore).map_with(|_, e| spanned(Expr::PrevAnswer, e.span())),
             expr.clone()
-                .delimited_by(just(Token::LParen), just(Token::RParen)),
+                .delimited_by(just(Token::LParen), just(Token::RParen))
+                .recover_with(via_parser(nested_delimiters(
+                    Token::LParen,
+                    Token::RParen,
+                    [],
+                    |_| Expr::Error,
+                ))),
         ))
         .boxed();
 
         let postfixed = atomic
-            .clone()
-            .then(choice((just(Token::Exclamation).to(PostfixOp::Fac),)))
-            .map(|(lhs, op)| Expr::PostfixOp {
-                op,
-                arg: Box::new(lhs),
+            .then(just(Token::Exclamation).to(PostfixOp::Fac).or_not())
+            .map_with(|(arg, op), e| match op {
+                Some(op) => {
+                    let expr = Expr::PostfixOp {
+                        op,
+                        arg: Box::new(arg),
+                    };
+                    spanned(expr, e.span())
+                }
+                None => arg,
             })
-            .or(atomic)
             .boxed();
 
-        let power = postfixed
-            .clone()
-            .then(just(Token::Caret).to(InfixOp::Pow))
-            .repeated()
-            .foldr(postfixed, |(lhs, op), rhs| Expr::InfixOp {
-                op,
-                lhs: Box::new(lhs),
-                rhs: Box::new(rhs),
-            })
-            .boxed();
+        let power = recursive(|power| {
+            postfixed
+                .then(just(Token::Caret).to(InfixOp::Pow).then(power).or_not())
+                .map_with(|(lhs, rhs), e| match rhs {
+                    Some((op, rhs)) => spanned(infix(op, lhs, rhs), e.span()),
+                    None => lhs,
+                })
+        })
+        .boxed();
 
         let term = power
             .clone()
-            .or(choice((just(Token::Minus).to(PrefixOp::Neg),))
-                .then(power.clone())
-                .map(|(op, rhs)| Expr::PrefixOp {
+            .or(choice((
+                just(Token::Minus).to(PrefixOp::Neg),
+                just(Token::Tilde).to(PrefixOp::BitNot),
+            ))
+            .then(power.clone())
+            .map_with(|(op, rhs), e| {
+                let expr = Expr::PrefixOp {
                     op,
                     arg: Box::new(rhs),
-                }))
+                };
+                spanned(expr, e.span())
+            }))
             .boxed();
 
         let powers = term
             .clone()
-            .foldl(
+            .foldl_with(
                 any()
                     .filter(|token| {
-                        !matches!(token, Token::Minus | Token::Int(_) | Token::Float(_))
+                        !matches!(
+                            token,
+                            Token::Minus | Token::Int(_) | Token::Float(_) | Token::Degrees(_)
+                        )
                     })
                     .rewind()
                     .ignore_then(term)
                     .repeated(),
-                |lhs, rhs| Expr::InfixOp {
-                    op: InfixOp::Mul,
-                    lhs: Box::new(lhs),
-                    rhs: Box::new(rhs),
-                },
+                |lhs, rhs, e| spanned(infix(InfixOp::Mul, lhs, rhs), e.span()),
             )
             .boxed();
 
         let product = powers
             .clone()
-            .foldl(
+            .foldl_with(
                 choice((
                     just(Token::Asterisk).to(InfixOp::Mul),
                     just(Token::Slash).to(InfixOp::Div),
@@ -157,31 +246,178 @@ where
                 ))
                 .then(powers)
                 .repeated(),
-                |lhs, (op, rhs)| Expr::InfixOp {
-                    op,
-                    lhs: Box::new(lhs),
-                    rhs: Box::new(rhs),
-                },
+                |lhs, (op, rhs), e| spanned(infix(op, lhs, rhs), e.span()),
             )
             .boxed();
 
         let sum = product
             .clone()
-            .foldl(
+            .foldl_with(
                 choice((
                     just(Token::Plus).to(InfixOp::Add),
                     just(Token::Minus).to(InfixOp::Sub),
                 ))
-                .then(product)
+                .then(product.clone())
                 .repeated(),
-                |lhs, (op, rhs)| Expr::InfixOp {
+                |lhs, (op, rhs), e| spanned(infix(op, lhs, rhs), e.span()),
+            )
+            .boxed();
+
+        let shift = sum
+            .clone()
+            .foldl_with(
+                choice((
+                    just(Token::DoubleLess).to(InfixOp::Shl),
+                    just(Token::DoubleGreater).to(InfixOp::Shr),
+                ))
+                .then(sum)
+                .repeated(),
+                |lhs, (op, rhs), e| spanned(infix(op, lhs, rhs), e.span()),
+            )
+            .boxed();
+
+        let bit_and = shift
+            .clone()
+            .foldl_with(
+                just(Token::Ampersand)
+                    .to(InfixOp::BitAnd)
+                    .then(shift)
+                    .repeated(),
+                |lhs, (op, rhs), e| spanned(infix(op, lhs, rhs), e.span()),
+            )
+            .boxed();
+
+        let bit_xor = bit_and
+            .clone()
+            .foldl_with(
+                just(Token::Xor)
+                    .to(InfixOp::BitXor)
+                    .then(bit_and)
+                    .repeated(),
+                |lhs, (op, rhs), e| spanned(infix(op, lhs, rhs), e.span()),
+            )
+            .boxed();
+
+        let bit_or = bit_xor
+            .clone()
+            .foldl_with(
+                just(Token::Pipe)
+                    .to(InfixOp::BitOr)
+                    .then(bit_xor)
+                    .repeated(),
+                |lhs, (op, rhs), e| spanned(infix(op, lhs, rhs), e.span()),
+            )
+            .boxed();
+
+        let conversion = bit_or
+            .foldl_with(
+                just(Token::To)
+                    .to(InfixOp::Convert)
+                    .then(product)
+                    .repeated(),
+                |lhs, (op, rhs), e| spanned(infix(op, lhs, rhs), e.span()),
+            )
+            .boxed();
+
+        let comparison = conversion
+            .clone()
+            .then(
+                choice((
+                    just(Token::DoubleEqual).to(InfixOp::Eq),
+                    just(Token::ExclamationEqual).to(InfixOp::Ne),
+                    just(Token::Less).to(InfixOp::Lt),
+                    just(Token::LessEqual).to(InfixOp::Le),
+                    just(Token::Greater).to(InfixOp::Gt),
+                    just(Token::GreaterEqual).to(InfixOp::Ge),
+                ))
+                .then(conversion)
+                .or_not(),
+            )
+            .map_with(|(lhs, rhs), e| match rhs {
+                Some((op, rhs)) => spanned(infix(op, lhs, rhs), e.span()),
+                None => lhs,
+            })
+            .boxed();
+
+        let negation = just(Token::Not)
+            .to(PrefixOp::Not)
+            .repeated()
+            .foldr_with(comparison, |op, arg, e| {
+                let expr = Expr::PrefixOp {
                     op,
-                    lhs: Box::new(lhs),
-                    rhs: Box::new(rhs),
-                },
+                    arg: Box::new(arg),
+                };
+                spanned(expr, e.span())
+            })
+            .boxed();
+
+        let conjunction = negation
+            .clone()
+            .foldl_with(
+                just(Token::And).to(InfixOp::And).then(negation).repeated(),
+                |lhs, (op, rhs), e| spanned(infix(op, lhs, rhs), e.span()),
+            )
+            .boxed();
+
+        let disjunction = conjunction
+            .clone()
+            .foldl_with(
+                just(Token::Or).to(InfixOp::Or).then(conjunction).repeated(),
+                |lhs, (op, rhs), e| spanned(infix(op, lhs, rhs), e.span()),
             )
             .boxed();
 
-        sum.labelled("expression").as_context()
+        let conditional = just(Token::If)
+            .ignore_then(expr.clone())
+            .then_ignore(just(Token::Then))
+            .then(expr.clone())
+            .then_ignore(just(Token::Else))
+            .then(expr)
+            .map_with(|((cond, then_expr), else_expr), e| {
+                let expr = Expr::If {
+                    cond: Box::new(cond),
+                    then_expr: Box::new(then_expr),
+                    else_expr: Box::new(else_expr),
+                };
+                spanned(expr, e.span())
+            })
+            .boxed();
+
+        conditional
+            .or(disjunction)
+            .labelled("expression")
+            .as_context()
     })
 }
+
+/// Recovers from a malformed name in a `let` definition by skipping up to
+/// the `=` or one of `until`, so the rest of the definition is still checked.
+fn skip_name<'a, I, const N: usize>(
+    until: [Token<'a>; N],
+) -> impl Parser<'a, I, String, extra::Err<Rich<'a, Token<'a>>>> + Clone
+where
+    I: ValueInput<'a, Token = Token<'a>, Span = SimpleSpan>,
+{
+    any()
+        .filter(move |token| *token != Token::Equal && !until.contains(token))
+        .repeated()
+        .at_least(1)
+        .to(String::new())
+}
+
+/// Wraps an expression with the span it was parsed from, so evaluation
+/// errors can point at it.
+fn spanned(expr: Expr, span: SimpleSpan) -> Expr {
+    Expr::Spanned {
+        expr: Box::new(expr),
+        span,
+    }
+}
+
+fn infix(op: InfixOp, lhs: Expr, rhs: Expr) -> Expr {
+    Expr::InfixOp {
+        op,
+        lhs: Box::new(lhs),
+        rhs: Box::new(rhs),
+    }
+}
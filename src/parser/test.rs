@@ -41,7 +41,7 @@ fn parse_expr(input: &str) -> Result<Expr, String> {
             Ok(tok) => (tok, span.into()),
             Err(()) => (Token::Error, span.into()),
         })
-        .filter(|(tok, _)| !matches!(tok, Token::Space));
+        .filter(|(tok, _)| !matches!(tok, Token::Space | Token::Comment));
 
     let token_stream = Stream::from_iter(token_iter.clone()).map((0..input.len()).into(), |x| x);
 
@@ -57,14 +57,24 @@ fn parse_stmt(input: &str) -> Result<Stmt, String> {
 
 #[test]
 fn number() {
-    assert_eq!(parse_expr("1"), Ok(Int(1)));
-    assert_eq!(parse_expr("   1"), Ok(Int(1)));
-    assert_eq!(parse_expr("0"), Ok(Int(0)));
+    assert_eq!(parse_expr("1"), Ok(Int(1.into())));
+    assert_eq!(parse_expr("   1"), Ok(Int(1.into())));
+    assert_eq!(parse_expr("0"), Ok(Int(0.into())));
+    assert_eq!(
+        parse_expr("123456789012345678901234567890"),
+        Ok(Int("123456789012345678901234567890".parse().unwrap()))
+    );
     assert_eq!(parse_expr("2.5"), Ok(Float(2.5)));
     assert_eq!(parse_expr("1e3"), Ok(Float(1e3)));
     assert_eq!(parse_expr("1e-3"), Ok(Float(1e-3)));
     assert_eq!(parse_expr("2.5e2"), Ok(Float(2.5e2)));
     assert_eq!(parse_expr("2.5e-2"), Ok(Float(2.5e-2)));
+    assert_eq!(parse_expr("0x1F"), Ok(Int(31.into())));
+    assert_eq!(parse_expr("0xdead_BEEF"), Ok(Int(0xdeadbeef_u32.into())));
+    assert_eq!(parse_expr("0o17"), Ok(Int(15.into())));
+    assert_eq!(parse_expr("0b1010_0101"), Ok(Int(165.into())));
+    assert_eq!(parse_expr("1_000_000"), Ok(Int(1_000_000.into())));
+    assert_eq!(parse_expr("1_000.000_5"), Ok(Float(1_000.000_5)));
 
     // Tests that should fail
     assert!(parse_expr("1..2").is_err());
@@ -77,16 +87,46 @@ fn number() {
 
 #[test]
 fn basic_ops() {
-    assert_eq!(parse_expr("6*3"), Ok(binop!(Mul, Int(6), Int(3))));
-    assert_eq!(parse_expr("6 * 3"), Ok(binop!(Mul, Int(6), Int(3))));
-    assert_eq!(parse_expr("6* 3"), Ok(binop!(Mul, Int(6), Int(3))));
-    assert_eq!(parse_expr("6 *3"), Ok(binop!(Mul, Int(6), Int(3))));
-    assert_eq!(parse_expr("6+3"), Ok(binop!(Add, Int(6), Int(3))));
-    assert_eq!(parse_expr("6-3"), Ok(binop!(Sub, Int(6), Int(3))));
-    assert_eq!(parse_expr("6/3"), Ok(binop!(Div, Int(6), Int(3))));
-    assert_eq!(parse_expr("6%3"), Ok(binop!(Rem, Int(6), Int(3))));
-    assert_eq!(parse_expr("2^3"), Ok(binop!(Pow, Int(2), Int(3))));
-    assert_eq!(parse_expr("6//3"), Ok(binop!(IntDiv, Int(6), Int(3))));
+    assert_eq!(
+        parse_expr("6*3"),
+        Ok(binop!(Mul, Int(6.into()), Int(3.into())))
+    );
+    assert_eq!(
+        parse_expr("6 * 3"),
+        Ok(binop!(Mul, Int(6.into()), Int(3.into())))
+    );
+    assert_eq!(
+        parse_expr("6* 3"),
+        Ok(binop!(Mul, Int(6.into()), Int(3.into())))
+    );
+    assert_eq!(
+        parse_expr("6 *3"),
+        Ok(binop!(Mul, Int(6.into()), Int(3.into())))
+    );
+    assert_eq!(
+        parse_expr("6+3"),
+        Ok(binop!(Add, Int(6.into()), Int(3.into())))
+    );
+    assert_eq!(
+        parse_expr("6-3"),
+        Ok(binop!(Sub, Int(6.into()), Int(3.into())))
+    );
+    assert_eq!(
+        parse_expr("6/3"),
+        Ok(binop!(Div, Int(6.into()), Int(3.into())))
+    );
+    assert_eq!(
+        parse_expr("6%3"),
+        Ok(binop!(Rem, Int(6.into()), Int(3.into())))
+    );
+    assert_eq!(
+        parse_expr("2^3"),
+        Ok(binop!(Pow, Int(2.into()), Int(3.into())))
+    );
+    assert_eq!(
+        parse_expr("6//3"),
+        Ok(binop!(IntDiv, Int(6.into()), Int(3.into())))
+    );
 
     // Different number notations
     assert_eq!(
@@ -99,7 +139,7 @@ fn basic_ops() {
     );
     assert_eq!(
         parse_expr("2.5e2 - 1"),
-        Ok(binop!(Sub, Float(2.5e2), Int(1)))
+        Ok(binop!(Sub, Float(2.5e2), Int(1.into())))
     );
     assert_eq!(
         parse_expr("2.5e-2 / 1e3"),
@@ -116,101 +156,145 @@ fn basic_ops() {
 
     assert_eq!(
         parse_expr("2 + 3 * 4"),
-        Ok(binop!(Add, Int(2), binop!(Mul, Int(3), Int(4))))
+        Ok(binop!(
+            Add,
+            Int(2.into()),
+            binop!(Mul, Int(3.into()), Int(4.into()))
+        ))
     );
     assert_eq!(
         parse_expr("(2 + 3) * 4"),
-        Ok(binop!(Mul, binop!(Add, Int(2), Int(3)), Int(4)))
+        Ok(binop!(
+            Mul,
+            binop!(Add, Int(2.into()), Int(3.into())),
+            Int(4.into())
+        ))
     );
     assert_eq!(
         parse_expr("2 * (3 + 4)"),
-        Ok(binop!(Mul, Int(2), binop!(Add, Int(3), Int(4))))
+        Ok(binop!(
+            Mul,
+            Int(2.into()),
+            binop!(Add, Int(3.into()), Int(4.into()))
+        ))
     );
     assert_eq!(
         parse_expr("2 * 3 + 4"),
-        Ok(binop!(Add, binop!(Mul, Int(2), Int(3)), Int(4)))
+        Ok(binop!(
+            Add,
+            binop!(Mul, Int(2.into()), Int(3.into())),
+            Int(4.into())
+        ))
     );
     assert_eq!(
         parse_expr("2 + 3 * 4 - 5 / 6"),
         Ok(binop!(
             Sub,
-            binop!(Add, Int(2), binop!(Mul, Int(3), Int(4))),
-            binop!(Div, Int(5), Int(6))
+            binop!(
+                Add,
+                Int(2.into()),
+                binop!(Mul, Int(3.into()), Int(4.into()))
+            ),
+            binop!(Div, Int(5.into()), Int(6.into()))
         ))
     );
     assert_eq!(
         parse_expr("2 * (3 + 4) - 5 % 6"),
         Ok(binop!(
             Sub,
-            binop!(Mul, Int(2), binop!(Add, Int(3), Int(4))),
-            binop!(Rem, Int(5), Int(6))
+            binop!(
+                Mul,
+                Int(2.into()),
+                binop!(Add, Int(3.into()), Int(4.into()))
+            ),
+            binop!(Rem, Int(5.into()), Int(6.into()))
         ))
     );
-    assert_eq!(parse_expr("5!"), Ok(postop!(Fac, Int(5))));
+    assert_eq!(parse_expr("5!"), Ok(postop!(Fac, Int(5.into()))));
     assert_eq!(
         parse_expr("-(2 + 3)"),
-        Ok(preop!(Neg, binop!(Add, Int(2), Int(3))))
+        Ok(preop!(Neg, binop!(Add, Int(2.into()), Int(3.into()))))
     );
     assert_eq!(
         parse_expr("-(2 * 3) + 4"),
         Ok(binop!(
             Add,
-            preop!(Neg, binop!(Mul, Int(2), Int(3))),
-            Int(4)
+            preop!(Neg, binop!(Mul, Int(2.into()), Int(3.into()))),
+            Int(4.into())
         ))
     );
     assert_eq!(
         parse_expr("2 * -(3 + 4)"),
         Ok(binop!(
             Mul,
-            Int(2),
-            preop!(Neg, binop!(Add, Int(3), Int(4)))
+            Int(2.into()),
+            preop!(Neg, binop!(Add, Int(3.into()), Int(4.into())))
         ))
     );
     assert_eq!(
         parse_expr("-(2 * 3 + 4)"),
         Ok(preop!(
             Neg,
-            binop!(Add, binop!(Mul, Int(2), Int(3)), Int(4))
+            binop!(
+                Add,
+                binop!(Mul, Int(2.into()), Int(3.into())),
+                Int(4.into())
+            )
         ))
     );
     assert_eq!(
         parse_expr("3! + 4"),
-        Ok(binop!(Add, postop!(Fac, Int(3)), Int(4)))
+        Ok(binop!(Add, postop!(Fac, Int(3.into())), Int(4.into())))
+    );
+    assert_eq!(
+        parse_expr("-(3!)"),
+        Ok(preop!(Neg, postop!(Fac, Int(3.into()))))
+    );
+    assert_eq!(
+        parse_expr("-3!"),
+        Ok(preop!(Neg, postop!(Fac, Int(3.into()))))
     );
-    assert_eq!(parse_expr("-(3!)"), Ok(preop!(Neg, postop!(Fac, Int(3)))));
-    assert_eq!(parse_expr("-3!"), Ok(preop!(Neg, postop!(Fac, Int(3)))));
     assert_eq!(
         parse_expr("2 ^ 3!"),
-        Ok(binop!(Pow, Int(2), postop!(Fac, Int(3))))
+        Ok(binop!(Pow, Int(2.into()), postop!(Fac, Int(3.into()))))
     );
     assert_eq!(
         parse_expr("-(2 ^ 3)"),
-        Ok(preop!(Neg, binop!(Pow, Int(2), Int(3))))
+        Ok(preop!(Neg, binop!(Pow, Int(2.into()), Int(3.into()))))
     );
     assert_eq!(
         parse_expr("-2^3"),
-        Ok(preop!(Neg, binop!(Pow, Int(2), Int(3))))
+        Ok(preop!(Neg, binop!(Pow, Int(2.into()), Int(3.into()))))
     );
     assert_eq!(
         parse_expr("2 ^ (-3)"),
-        Ok(binop!(Pow, Int(2), preop!(Neg, Int(3))))
+        Ok(binop!(Pow, Int(2.into()), preop!(Neg, Int(3.into()))))
     );
     assert_eq!(
         parse_expr("-(2 ^ (-3))"),
-        Ok(preop!(Neg, binop!(Pow, Int(2), preop!(Neg, Int(3)))))
+        Ok(preop!(
+            Neg,
+            binop!(Pow, Int(2.into()), preop!(Neg, Int(3.into())))
+        ))
+    );
+    assert_eq!(
+        parse_expr("-(-3)"),
+        Ok(preop!(Neg, preop!(Neg, Int(3.into()))))
     );
-    assert_eq!(parse_expr("-(-3)"), Ok(preop!(Neg, preop!(Neg, Int(3)))));
     assert_eq!(
         parse_expr("-2 (-3)"),
-        Ok(binop!(Mul, preop!(Neg, Int(2)), preop!(Neg, Int(3))))
+        Ok(binop!(
+            Mul,
+            preop!(Neg, Int(2.into())),
+            preop!(Neg, Int(3.into()))
+        ))
     );
     assert_eq!(
         parse_expr("(5 + 3)  (-3)"),
         Ok(binop!(
             Mul,
-            binop!(Add, Int(5), Int(3)),
-            preop!(Neg, Int(3))
+            binop!(Add, Int(5.into()), Int(3.into())),
+            preop!(Neg, Int(3.into()))
         ))
     );
 
@@ -250,7 +334,7 @@ fn function_calls() {
         parse_expr("sin(3)"),
         Ok(Expr::FnCall {
             name: String::from("sin"),
-            args: vec![Int(3)],
+            args: vec![Int(3.into())],
         })
     );
 
@@ -259,14 +343,14 @@ fn function_calls() {
         parse_expr("log(1, 10)"),
         Ok(Expr::FnCall {
             name: String::from("log"),
-            args: vec![Int(1), Int(10)],
+            args: vec![Int(1.into()), Int(10.into())],
         })
     );
     assert_eq!(
         parse_expr("log(2.5, 10)"),
         Ok(Expr::FnCall {
             name: String::from("log"),
-            args: vec![Float(2.5), Int(10)],
+            args: vec![Float(2.5), Int(10.into())],
         })
     );
     assert_eq!(
@@ -282,7 +366,7 @@ fn function_calls() {
             Fac,
             Expr::FnCall {
                 name: String::from("log"),
-                args: vec![Int(1), Int(10)],
+                args: vec![Int(1.into()), Int(10.into())],
             }
         ))
     );
@@ -305,25 +389,33 @@ fn mathematical_notations() {
         parse_expr("2 sin(3)"),
         Ok(binop!(
             Mul,
-            Int(2),
+            Int(2.into()),
             Expr::FnCall {
                 name: String::from("sin"),
-                args: vec![Int(3)],
+                args: vec![Int(3.into())],
             }
         ))
     );
     assert_eq!(
         parse_expr("2 (5 + 2)"),
-        Ok(binop!(Mul, Int(2), binop!(Add, Int(5), Int(2))))
+        Ok(binop!(
+            Mul,
+            Int(2.into()),
+            binop!(Add, Int(5.into()), Int(2.into()))
+        ))
     );
     assert_eq!(
         parse_expr("3 (4 + 5) sin(6)"),
         Ok(binop!(
             Mul,
-            binop!(Mul, Int(3), binop!(Add, Int(4), Int(5))),
+            binop!(
+                Mul,
+                Int(3.into()),
+                binop!(Add, Int(4.into()), Int(5.into()))
+            ),
             Expr::FnCall {
                 name: String::from("sin"),
-                args: vec![Int(6)],
+                args: vec![Int(6.into())],
             }
         ))
     );
@@ -331,18 +423,22 @@ fn mathematical_notations() {
         parse_expr("2 (3 + 4) (5 + 6)"),
         Ok(binop!(
             Mul,
-            binop!(Mul, Int(2), binop!(Add, Int(3), Int(4))),
-            binop!(Add, Int(5), Int(6))
+            binop!(
+                Mul,
+                Int(2.into()),
+                binop!(Add, Int(3.into()), Int(4.into()))
+            ),
+            binop!(Add, Int(5.into()), Int(6.into()))
         ))
     );
     assert_eq!(
         parse_expr("2 sin(3 + 4)"),
         Ok(binop!(
             Mul,
-            Int(2),
+            Int(2.into()),
             Expr::FnCall {
                 name: String::from("sin"),
-                args: vec![binop!(Add, Int(3), Int(4))],
+                args: vec![binop!(Add, Int(3.into()), Int(4.into()))],
             }
         ))
     );
@@ -350,13 +446,13 @@ fn mathematical_notations() {
         parse_expr("2 (3 + sin(4))"),
         Ok(binop!(
             Mul,
-            Int(2),
+            Int(2.into()),
             binop!(
                 Add,
-                Int(3),
+                Int(3.into()),
                 Expr::FnCall {
                     name: String::from("sin"),
-                    args: vec![Int(4)],
+                    args: vec![Int(4.into())],
                 }
             )
         ))
@@ -385,19 +481,19 @@ fn mixed_operations_and_number_notations() {
             Mul,
             Expr::FnCall {
                 name: String::from("sin"),
-                args: vec![binop!(Add, Int(2), Int(3))],
+                args: vec![binop!(Add, Int(2.into()), Int(3.into()))],
             },
-            Int(4)
+            Int(4.into())
         ))
     );
     assert_eq!(
         parse_expr("2 * log(3 + 4, 10)"),
         Ok(binop!(
             Mul,
-            Int(2),
+            Int(2.into()),
             Expr::FnCall {
                 name: String::from("log"),
-                args: vec![binop!(Add, Int(3), Int(4)), Int(10)],
+                args: vec![binop!(Add, Int(3.into()), Int(4.into())), Int(10.into())],
             }
         ))
     );
@@ -408,15 +504,15 @@ fn mixed_operations_and_number_notations() {
             Sub,
             binop!(
                 Mul,
-                Int(2),
+                Int(2.into()),
                 Expr::FnCall {
                     name: String::from("sin"),
-                    args: vec![binop!(Add, Int(3), Int(4))],
+                    args: vec![binop!(Add, Int(3.into()), Int(4.into()))],
                 }
             ),
             Expr::FnCall {
                 name: String::from("log"),
-                args: vec![Int(5), Int(6)],
+                args: vec![Int(5.into()), Int(6.into())],
             }
         ))
     );
@@ -424,13 +520,13 @@ fn mixed_operations_and_number_notations() {
         parse_expr("2 * (3 + sin(4))"),
         Ok(binop!(
             Mul,
-            Int(2),
+            Int(2.into()),
             binop!(
                 Add,
-                Int(3),
+                Int(3.into()),
                 Expr::FnCall {
                     name: String::from("sin"),
-                    args: vec![Int(4)],
+                    args: vec![Int(4.into())],
                 }
             )
         ))
@@ -456,7 +552,7 @@ fn mixed_operations_and_number_notations() {
                 name: String::from("log"),
                 args: vec![Float(1e-3), Float(2.5)],
             },
-            Int(10)
+            Int(10.into())
         ))
     );
     assert_eq!(
@@ -465,7 +561,7 @@ fn mixed_operations_and_number_notations() {
             Sub,
             binop!(
                 Mul,
-                Int(2),
+                Int(2.into()),
                 Expr::FnCall {
                     name: String::from("sin"),
                     args: vec![Float(2.5e2)],
@@ -473,7 +569,7 @@ fn mixed_operations_and_number_notations() {
             ),
             Expr::FnCall {
                 name: String::from("log"),
-                args: vec![Int(1), Float(1e3)],
+                args: vec![Int(1.into()), Float(1e3)],
             }
         ))
     );
@@ -487,13 +583,184 @@ fn mixed_operations_and_number_notations() {
     assert!(parse_expr("2 * (3 + sin(4.5.6))").is_err()); // Invalid number format
 }
 
+#[test]
+fn unit_conversion() {
+    assert_eq!(
+        parse_expr("3 km / 20 min to mph"),
+        Ok(binop!(
+            Convert,
+            binop!(
+                Div,
+                binop!(Mul, Int(3.into()), Variable(String::from("km"))),
+                binop!(Mul, Int(20.into()), Variable(String::from("min")))
+            ),
+            Variable(String::from("mph"))
+        ))
+    );
+    assert_eq!(
+        parse_expr("_ in km/h"),
+        Ok(binop!(
+            Convert,
+            PrevAnswer,
+            binop!(
+                Div,
+                Variable(String::from("km")),
+                Variable(String::from("h"))
+            )
+        ))
+    );
+
+    // Failing tests
+    assert!(parse_expr("1 km to").is_err());
+    assert!(parse_expr("to km").is_err());
+}
+
+#[test]
+fn angle_literals() {
+    let angle = |magnitude: f64, unit: &str| Quantity {
+        magnitude,
+        unit: unit.to_string(),
+    };
+
+    assert_eq!(parse_expr("30°"), Ok(angle(30., "deg")));
+    assert_eq!(parse_expr("30deg"), Ok(angle(30., "deg")));
+    assert_eq!(parse_expr("1.2rad"), Ok(angle(1.2, "rad")));
+    assert_eq!(parse_expr("12°30'"), Ok(angle(12.5, "deg")));
+    assert_eq!(parse_expr("12°30'36\""), Ok(angle(12.51, "deg")));
+    assert_eq!(parse_expr("1°36\""), Ok(angle(1.01, "deg")));
+    assert_eq!(
+        parse_expr("sin(30°)"),
+        Ok(FnCall {
+            name: String::from("sin"),
+            args: vec![angle(30., "deg")],
+        })
+    );
+    assert_eq!(
+        parse_expr("2rad(30)"),
+        Ok(binop!(
+            Mul,
+            Int(2.into()),
+            FnCall {
+                name: String::from("rad"),
+                args: vec![Int(30.into())],
+            }
+        ))
+    );
+    // a name starting with an angle unit is not an angle literal
+    assert_eq!(
+        parse_expr("2radius"),
+        Ok(binop!(Mul, Int(2.into()), Variable(String::from("radius"))))
+    );
+    assert_eq!(
+        parse_expr("2degrees"),
+        Ok(binop!(
+            Mul,
+            Int(2.into()),
+            Variable(String::from("degrees"))
+        ))
+    );
+    assert!(parse_expr("2 30deg").is_err());
+}
+
+#[test]
+fn bitwise_operators() {
+    let var = |name: &str| Variable(name.to_string());
+
+    assert_eq!(
+        parse_expr("a | b xor c & d"),
+        Ok(binop!(
+            BitOr,
+            var("a"),
+            binop!(BitXor, var("b"), binop!(BitAnd, var("c"), var("d")))
+        ))
+    );
+    assert_eq!(
+        parse_expr("1 << 2 + 3 >> 1"),
+        Ok(binop!(
+            Shr,
+            binop!(
+                Shl,
+                Int(1.into()),
+                binop!(Add, Int(2.into()), Int(3.into()))
+            ),
+            Int(1.into())
+        ))
+    );
+    assert_eq!(
+        parse_expr("~x & 0xFF"),
+        Ok(binop!(BitAnd, preop!(BitNot, var("x")), Int(255.into())))
+    );
+    assert_eq!(
+        parse_expr("x & 1 == 0"),
+        Ok(binop!(
+            Eq,
+            binop!(BitAnd, var("x"), Int(1.into())),
+            Int(0.into())
+        ))
+    );
+
+    assert!(parse_expr("1 & & 2").is_err());
+    assert!(parse_expr("1 <<").is_err());
+}
+
+#[test]
+fn comparisons_and_conditionals() {
+    assert_eq!(
+        parse_expr("1 + 2 <= 3"),
+        Ok(binop!(
+            Le,
+            binop!(Add, Int(1.into()), Int(2.into())),
+            Int(3.into())
+        ))
+    );
+    assert_eq!(
+        parse_expr("not x == 1 and y != 2 or z"),
+        Ok(binop!(
+            Or,
+            binop!(
+                And,
+                preop!(Not, binop!(Eq, Variable(String::from("x")), Int(1.into()))),
+                binop!(Ne, Variable(String::from("y")), Int(2.into()))
+            ),
+            Variable(String::from("z"))
+        ))
+    );
+    assert_eq!(
+        parse_expr("if x > 0 then x else -x"),
+        Ok(If {
+            cond: binop!(Gt, Variable(String::from("x")), Int(0.into())).into(),
+            then_expr: Variable(String::from("x")).into(),
+            else_expr: preop!(Neg, Variable(String::from("x"))).into(),
+        })
+    );
+    assert_eq!(
+        parse_expr("if a then 1 else if b then 2 else 3"),
+        Ok(If {
+            cond: Variable(String::from("a")).into(),
+            then_expr: Int(1.into()).into(),
+            else_expr: If {
+                cond: Variable(String::from("b")).into(),
+                then_expr: Int(2.into()).into(),
+                else_expr: Int(3.into()).into(),
+            }
+            .into(),
+        })
+    );
+
+    // Failing tests
+    assert!(parse_expr("1 < 2 < 3").is_err());
+    assert!(parse_expr("if x then 1").is_err());
+    assert!(parse_expr("if x 1 else 2").is_err());
+    assert!(parse_expr("1 and").is_err());
+}
+
 #[test]
 fn variable_definition_test() {
     assert_eq!(
         parse_stmt("let x = 42"),
         Ok(Stmt::DefVar {
             name: String::from("x"),
-            expr: Expr::Int(42),
+            expr: Expr::Int(42.into()),
         })
     );
 
@@ -517,6 +784,14 @@ fn variable_definition_test() {
         })
     );
 
+    assert_eq!(
+        parse_stmt("let r = 2 # radius"),
+        Ok(Stmt::DefVar {
+            name: String::from("r"),
+            expr: Expr::Int(2.into()),
+        })
+    );
+
     // Failing tests
     assert!(parse_stmt("let = 42").is_err());
     assert!(parse_stmt("let x 42").is_err());
@@ -536,6 +811,8 @@ fn function_definition_test() {
                 Expr::Variable(String::from("a")),
                 Expr::Variable(String::from("b"))
             ),
+            source: String::from("a + b"),
+            doc: None,
         })
     );
 
@@ -549,6 +826,8 @@ fn function_definition_test() {
                 Expr::Variable(String::from("x")),
                 Expr::Variable(String::from("x"))
             ),
+            source: String::from("x * x"),
+            doc: None,
         })
     );
 
@@ -558,6 +837,19 @@ fn function_definition_test() {
             name: String::from("negate"),
             arg_names: vec![String::from("x")],
             body: preop!(Neg, Expr::Variable(String::from("x"))),
+            source: String::from("-x"),
+            doc: None,
+        })
+    );
+
+    assert_eq!(
+        parse_stmt("let sq(x) = x^2  # the square of x"),
+        Ok(Stmt::DefFun {
+            name: String::from("sq"),
+            arg_names: vec![String::from("x")],
+            body: binop!(Pow, Expr::Variable(String::from("x")), Int(2.into())),
+            source: String::from("x^2"),
+            doc: Some(String::from("the square of x")),
         })
     );
 
@@ -567,3 +859,89 @@ fn function_definition_test() {
     assert!(parse_stmt("let add(a b) = a + b").is_err());
     assert!(parse_stmt("let add(a, b) a + b").is_err());
 }
+
+#[test]
+fn display_mode_suffix() {
+    use crate::models::{DisplayMode, Radix};
+
+    assert_eq!(
+        parse_stmt("255 as hex"),
+        Ok(Stmt::Formatted {
+            stmt: Box::new(Stmt::Expr(Int(255.into()))),
+            mode: DisplayMode::Radix(Radix::Hex, None),
+        })
+    );
+
+    assert_eq!(
+        parse_stmt("let x = 1 - 2 as bin8"),
+        Ok(Stmt::Formatted {
+            stmt: Box::new(Stmt::DefVar {
+                name: String::from("x"),
+                expr: binop!(Sub, Int(1.into()), Int(2.into())),
+            }),
+            mode: DisplayMode::Radix(Radix::Binary, Some(8)),
+        })
+    );
+
+    assert_eq!(
+        parse_stmt("3 km to m as eng"),
+        Ok(Stmt::Formatted {
+            stmt: Box::new(Stmt::Expr(binop!(
+                Convert,
+                binop!(Mul, Int(3.into()), Expr::Variable(String::from("km"))),
+                Expr::Variable(String::from("m"))
+            ))),
+            mode: DisplayMode::Engineering,
+        })
+    );
+
+    // Failing tests
+    assert!(parse_stmt("255 as").is_err());
+    assert!(parse_stmt("255 as roman").is_err());
+    assert!(parse_stmt("255 as hex as bin").is_err());
+    assert!(parse_stmt("let f(x) = x as hex").is_err());
+    assert!(parse_stmt("(255 as hex) + 1").is_err());
+}
+
+#[test]
+fn error_recovery() {
+    let errors = |input| {
+        crate::lex_and_parse(input)
+            .unwrap_err()
+            .into_iter()
+            .map(|err| (err.message, err.span))
+            .collect::<Vec<_>>()
+    };
+
+    assert_eq!(
+        errors("(1 + 2"),
+        [(
+            String::from("expected an operator or `)`, found end of input"),
+            6..6
+        )]
+    );
+    assert_eq!(
+        errors("let x = (1 +) * max(2, )"),
+        [
+            (String::from("expected an expression, found `)`"), 12..13),
+            (String::from("expected an expression, found `)`"), 23..24),
+        ]
+    );
+    assert_eq!(
+        errors("let 2x = 3 +"),
+        [
+            (String::from("expected a name, found `2`"), 4..5),
+            (
+                String::from("expected an expression, found end of input"),
+                12..12
+            ),
+        ]
+    );
+    assert_eq!(
+        errors("let f(1, x) = (x *)"),
+        [
+            (String::from("expected a name or `)`, found `1`"), 6..7),
+            (String::from("expected an expression, found `)`"), 18..19),
+        ]
+    );
+}
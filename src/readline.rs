@@ -1,25 +1,184 @@
-use std::borrow::Cow;
+use std::{borrow::Cow, cell::RefCell, rc::Rc};
 
-use crate::models::Token;
+use crate::{
+    commands::{self, COMMANDS, SETTINGS},
+    theme::Theme,
+};
 use colored::Colorize;
+use evar::{
+    Evaluator,
+    models::{DISPLAY_MODES, EvalError, SevaError, Token},
+};
 use logos::Logos;
 use rustyline::{
-    Completer, Config, Editor, Helper, Highlighter, Hinter, Validator, error::ReadlineError,
-    highlight::Highlighter, hint::HistoryHinter, history::FileHistory,
+    Completer, Config, Editor, Helper, Highlighter, Hinter, Validator,
+    completion::{Completer, FilenameCompleter, Pair},
+    error::ReadlineError,
+    highlight::Highlighter,
+    hint::{Hint, Hinter, HistoryHinter},
+    history::FileHistory,
     validate::MatchingBracketValidator,
 };
 
 #[derive(Helper, Completer, Hinter, Validator, Highlighter)]
 struct RustyLineHelper {
+    #[rustyline(Completer)]
+    completer: SevaCompleter,
     #[rustyline(Validator)]
     validator: MatchingBracketValidator,
     #[rustyline(Hinter)]
-    hinter: HistoryHinter,
+    hinter: SevaHinter,
     #[rustyline(Highlighter)]
     highlighter: SevaHighlighter,
 }
 
-struct SevaHighlighter;
+/// Completes function and variable names from the live context, and REPL
+/// commands with their arguments.
+struct SevaCompleter {
+    evaluator: Rc<RefCell<Evaluator>>,
+    files: FilenameCompleter,
+}
+
+/// Candidates from `names` that start with `word`.
+fn matching<'a>(names: impl IntoIterator<Item = &'a str>, word: &str) -> Vec<Pair> {
+    names
+        .into_iter()
+        .filter(|name| name.starts_with(word))
+        .map(|name| Pair {
+            display: name.to_string(),
+            replacement: name.to_string(),
+        })
+        .collect()
+}
+
+impl Completer for SevaCompleter {
+    type Candidate = Pair;
+
+    fn complete(
+        &self,
+        line: &str,
+        pos: usize,
+        ctx: &rustyline::Context<'_>,
+    ) -> rustyline::Result<(usize, Vec<Pair>)> {
+        let start = line[..pos]
+            .rfind(|c: char| !(c.is_alphanumeric() || c == '_' || c == ':'))
+            .map_or(0, |i| i + 1);
+        let word = &line[start..pos];
+        let before = line[..start].trim_start();
+
+        let mut candidates = match before.split_whitespace().collect::<Vec<_>>()[..] {
+            [] => matching(COMMANDS, word),
+            [":set"] => matching(SETTINGS, word),
+            [":mode"] | [":set", "mode"] => matching(["rad", "deg", "grad"], word),
+            [":set", "format"] => matching(DISPLAY_MODES, word),
+            [.., "as"] if !before.starts_with(':') => {
+                return Ok((start, matching(DISPLAY_MODES, word)));
+            }
+            [":load" | ":save", ..] => return self.files.complete(line, pos, ctx),
+            [":help" | "help"] => {
+                let evaluator = self.evaluator.borrow();
+                let functions = evaluator.functions().map(|(name, _)| name.as_str());
+                let variables = evaluator.variables().map(|(name, _)| name.as_str());
+                matching(functions.chain(variables), word)
+            }
+            [":del"] => {
+                let evaluator = self.evaluator.borrow();
+                let variables = evaluator
+                    .variables()
+                    .filter(|(_, variable)| !variable.is_external())
+                    .map(|(name, _)| name.as_str());
+                let functions = evaluator
+                    .functions()
+                    .filter(|(_, function)| !function.is_external())
+                    .map(|(name, _)| name.as_str());
+                matching(variables.chain(functions), word)
+            }
+            _ => Vec::new(),
+        };
+
+        if !word.starts_with(':') && !before.starts_with(':') && before.trim_end() != "help" {
+            let evaluator = self.evaluator.borrow();
+            candidates.extend(
+                evaluator
+                    .functions()
+                    .filter(|(name, _)| name.starts_with(word))
+                    .map(|(name, function)| Pair {
+                        display: format!("{}/{}", name, function.arity()),
+                        replacement: format!("{}(", name),
+                    }),
+            );
+            candidates.extend(matching(
+                evaluator.variables().map(|(name, _)| name.as_str()),
+                word,
+            ));
+        }
+
+        candidates.sort_by(|a, b| a.display.cmp(&b.display));
+        Ok((start, candidates))
+    }
+}
+
+/// Previews the result of the line being typed, falling back to history
+/// suggestions for commands and while the line does not parse. Previews that
+/// run out of steps show nothing.
+struct SevaHinter {
+    evaluator: Rc<RefCell<Evaluator>>,
+    history: HistoryHinter,
+}
+
+enum SevaHint {
+    Result(String),
+    History(String),
+}
+
+impl Hint for SevaHint {
+    fn display(&self) -> &str {
+        match self {
+            SevaHint::Result(hint) | SevaHint::History(hint) => hint,
+        }
+    }
+
+    fn completion(&self) -> Option<&str> {
+        match self {
+            SevaHint::Result(_) => None,
+            SevaHint::History(hint) => Some(hint),
+        }
+    }
+}
+
+impl Hinter for SevaHinter {
+    type Hint = SevaHint;
+
+    fn hint(&self, line: &str, pos: usize, ctx: &rustyline::Context<'_>) -> Option<SevaHint> {
+        if line.trim().is_empty() || pos < line.len() {
+            return None;
+        }
+
+        if commands::is_command(line) {
+            return self.history.hint(line, pos, ctx).map(SevaHint::History);
+        }
+
+        let evaluator = self.evaluator.borrow();
+        let Ok(stmt) = evaluator.parse(line) else {
+            return self.history.hint(line, pos, ctx).map(SevaHint::History);
+        };
+        let context = evaluator.context();
+        let display_mode = stmt.display_mode().unwrap_or(context.display_mode());
+        match evaluator.preview_stmt(stmt) {
+            Ok(value) if value.is_null() => None,
+            Err(err) if matches!(err.root(), EvalError::StepLimit) => None,
+            Ok(value) => {
+                let value = value.format(context.fix(), display_mode);
+                Some(SevaHint::Result(format!(" = {}", value)))
+            }
+            Err(err) => Some(SevaHint::Result(format!(" ({})", err))),
+        }
+    }
+}
+
+struct SevaHighlighter {
+    theme: Theme,
+}
 
 impl Highlighter for SevaHighlighter {
     fn highlight<'l>(&self, line: &'l str, _pos: usize) -> std::borrow::Cow<'l, str> {
@@ -28,24 +187,49 @@ impl Highlighter for SevaHighlighter {
         let highlighted_line = tokens.fold(String::new(), |acc, (lex_result, span)| {
             acc + &{
                 match lex_result {
-                    Err(_) => format!("{}", line[span].truecolor(237, 135, 150)),
+                    Err(_) => format!("{}", line[span].color(self.theme.error)),
                     Ok(token) => match token {
-                        Token::Int(_) | Token::Float(_) => {
-                            format!("{}", line[span].truecolor(245, 169, 127))
+                        Token::Int(_) | Token::Float(_) | Token::Degrees(_) => {
+                            format!("{}", line[span].color(self.theme.number))
                         }
-                        Token::Ident(_) => format!("{}", line[span].truecolor(138, 173, 244)),
+                        Token::Ident(_) => format!("{}", line[span].color(self.theme.ident)),
                         Token::Plus
                         | Token::Minus
                         | Token::Asterisk
                         | Token::Slash
                         | Token::Percent
                         | Token::Caret
-                        | Token::Exclamation => format!("{}", line[span].truecolor(125, 196, 228)),
+                        | Token::Exclamation
+                        | Token::DoubleEqual
+                        | Token::ExclamationEqual
+                        | Token::Less
+                        | Token::LessEqual
+                        | Token::Greater
+                        | Token::GreaterEqual
+                        | Token::Ampersand
+                        | Token::Pipe
+                        | Token::Tilde
+                        | Token::DoubleLess
+                        | Token::DoubleGreater => {
+                            format!("{}", line[span].color(self.theme.operator))
+                        }
                         Token::LParen | Token::RParen => {
-                            format!("{}", line[span].truecolor(238, 212, 159))
+                            format!("{}", line[span].color(self.theme.paren))
+                        }
+                        Token::Let
+                        | Token::To
+                        | Token::As
+                        | Token::If
+                        | Token::Then
+                        | Token::Else
+                        | Token::And
+                        | Token::Or
+                        | Token::Not
+                        | Token::Xor => {
+                            format!("{}", line[span].color(self.theme.keyword))
                         }
-                        Token::Let => format!("{}", line[span].truecolor(198, 160, 246)),
-                        Token::Equal => format!("{}", line[span].truecolor(125, 196, 228)),
+                        Token::Equal => format!("{}", line[span].color(self.theme.operator)),
+                        Token::Comment => format!("{}", line[span].color(self.theme.comment)),
                         _ => line[span].to_string(),
                     },
                 }
@@ -64,21 +248,29 @@ impl Highlighter for SevaHighlighter {
         kind != CmdKind::MoveCursor && kind != CmdKind::ForcedRefresh
     }
     fn highlight_hint<'h>(&self, hint: &'h str) -> Cow<'h, str> {
-        format!("{}", hint.truecolor(91, 96, 120)).into()
+        format!("{}", hint.color(self.theme.comment)).into()
     }
     fn highlight_prompt<'b, 's: 'b, 'p: 'b>(
         &'s self,
         prompt: &'p str,
         _default: bool,
     ) -> Cow<'b, str> {
-        format!("{}", prompt.truecolor(166, 218, 149)).into()
+        format!("{}", prompt.color(self.theme.prompt)).into()
     }
 }
 
-pub struct SevaEditor(Editor<RustyLineHelper, FileHistory>);
+pub struct SevaEditor {
+    editor: Editor<RustyLineHelper, FileHistory>,
+    prompt: String,
+}
 
 impl SevaEditor {
-    pub fn new(no_color: bool) -> SevaEditor {
+    pub fn new(
+        no_color: bool,
+        prompt: String,
+        theme: Theme,
+        evaluator: Rc<RefCell<Evaluator>>,
+    ) -> SevaEditor {
         if no_color {
             colored::control::set_override(false);
         }
@@ -90,26 +282,144 @@ impl SevaEditor {
             .build();
 
         let helper = RustyLineHelper {
+            completer: SevaCompleter {
+                evaluator: evaluator.clone(),
+                files: FilenameCompleter::new(),
+            },
             validator: MatchingBracketValidator::new(),
-            hinter: HistoryHinter::new(),
-            highlighter: SevaHighlighter,
+            hinter: SevaHinter {
+                evaluator,
+                history: HistoryHinter::new(),
+            },
+            highlighter: SevaHighlighter { theme },
         };
 
         let mut editor = Editor::with_config(editor_config).expect("failed to create editor");
         editor.set_helper(Some(helper));
         editor.bind_sequence(rustyline::KeyEvent::ctrl('f'), rustyline::Cmd::CompleteHint);
-        SevaEditor(editor)
+        SevaEditor { editor, prompt }
     }
 
     pub fn readline(&mut self) -> Result<String, ReadlineError> {
-        self.0.readline("> ")
+        self.editor.readline(&self.prompt)
+    }
+
+    pub fn history(&self) -> impl Iterator<Item = &String> {
+        self.editor.history().iter()
     }
 
-    pub fn load_history(&mut self, path: &std::path::Path) -> Result<(), crate::models::SevaError> {
-        self.0.load_history(path).map_err(|e| e.into())
+    pub fn load_history(&mut self, path: &std::path::Path) -> Result<(), SevaError> {
+        self.editor.load_history(path).map_err(|e| e.into())
     }
 
-    pub fn save_history(&mut self, path: &std::path::Path) -> Result<(), crate::models::SevaError> {
-        self.0.save_history(path).map_err(|e| e.into())
+    pub fn save_history(&mut self, path: &std::path::Path) -> Result<(), SevaError> {
+        self.editor.save_history(path).map_err(|e| e.into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use evar::models::DisplayMode;
+    use rustyline::history::DefaultHistory;
+
+    fn complete(completer: &SevaCompleter, line: &str) -> (usize, Vec<(String, String)>) {
+        let history = DefaultHistory::new();
+        let ctx = rustyline::Context::new(&history);
+        let (start, candidates) = completer.complete(line, line.len(), &ctx).unwrap();
+        let candidates = candidates
+            .into_iter()
+            .map(|pair| (pair.display, pair.replacement))
+            .collect();
+        (start, candidates)
+    }
+
+    #[test]
+    fn test_completion() {
+        let evaluator = Rc::new(RefCell::new(Evaluator::default()));
+        let completer = SevaCompleter {
+            evaluator: evaluator.clone(),
+            files: FilenameCompleter::new(),
+        };
+
+        let pair =
+            |display: &str, replacement: &str| (display.to_string(), replacement.to_string());
+
+        assert_eq!(
+            complete(&completer, "2 + sq"),
+            (4, vec![pair("sqrt/1", "sqrt(")])
+        );
+
+        evaluator.borrow_mut().eval("let square(x) = x^2").unwrap();
+        evaluator.borrow_mut().eval("let speed = 3").unwrap();
+        assert_eq!(
+            complete(&completer, "sq"),
+            (
+                0,
+                vec![pair("sqrt/1", "sqrt("), pair("square/1", "square(")]
+            )
+        );
+        assert_eq!(
+            complete(&completer, "spe"),
+            (0, vec![pair("speed", "speed")])
+        );
+        assert_eq!(
+            complete(&completer, ":m"),
+            (0, vec![pair(":mode", ":mode")])
+        );
+        assert_eq!(
+            complete(&completer, ":mode d"),
+            (6, vec![pair("deg", "deg")])
+        );
+        assert_eq!(
+            complete(&completer, "255 as h"),
+            (7, vec![pair("hex", "hex")])
+        );
+        assert_eq!(
+            complete(&completer, ":set format s"),
+            (12, vec![pair("sci", "sci"), pair("si", "si")])
+        );
+        assert_eq!(
+            complete(&completer, "help squ"),
+            (5, vec![pair("square", "square")])
+        );
+    }
+
+    #[test]
+    fn test_result_hint() {
+        let evaluator = Rc::new(RefCell::new(Evaluator::default()));
+        let hinter = SevaHinter {
+            evaluator: evaluator.clone(),
+            history: HistoryHinter::new(),
+        };
+        let history = DefaultHistory::new();
+        let ctx = rustyline::Context::new(&history);
+        let hint = |line: &str| {
+            hinter
+                .hint(line, line.len(), &ctx)
+                .map(|hint| hint.display().to_string())
+        };
+
+        assert_eq!(hint("1/4 + 1/4"), Some(String::from(" = 1/2")));
+        assert_eq!(hint("let x = 2^10"), Some(String::from(" = 1024")));
+        assert_eq!(hint("1/0"), Some(String::from(" (division by zero)")));
+        assert_eq!(hint("1 +"), None);
+        assert_eq!(hint("let f(x) = x"), None);
+        assert_eq!(hint("-1 as hex8"), Some(String::from(" = 0xff")));
+        evaluator
+            .borrow_mut()
+            .context_mut()
+            .set_display_mode(DisplayMode::Si);
+        assert_eq!(hint("4700 m"), Some(String::from(" = 4.7k m")));
+        assert!(evaluator.borrow().variable("x").is_none());
+        assert_eq!(hint("help sin"), None);
+        assert_eq!(hint(":vars"), None);
+
+        evaluator
+            .borrow_mut()
+            .eval("let g(n) = if n == 0 then 1 else g(n - 1) + g(n - 1)")
+            .unwrap();
+        assert_eq!(hint("g(3)"), Some(String::from(" = 8")));
+        assert_eq!(hint("g(16)"), None);
     }
 }
@@ -0,0 +1,455 @@
+use crate::{
+    commands::{self, Command, Setting},
+    error_report::{ErrorReporter, Location},
+    json::{CommandReport, StatementReport},
+    readline::SevaEditor,
+};
+use evar::{Error, Evaluator, models::Function};
+use std::{
+    cell::RefCell,
+    fs::File,
+    io::{BufRead, BufReader},
+    path::{Path, PathBuf},
+    rc::Rc,
+    time::Instant,
+};
+
+/// Evaluation state shared by every input of one evar run.
+pub struct Session {
+    pub evaluator: Rc<RefCell<Evaluator>>,
+    /// The evaluator as it was after init.evar ran, restored by `:reset`.
+    initial: Evaluator,
+    reporter: ErrorReporter,
+    debug: bool,
+    json: bool,
+    /// Script files being run, outermost first, so a script cannot load
+    /// itself.
+    loading: Vec<PathBuf>,
+}
+
+/// What happened to one line of input.
+#[derive(Clone, Copy, PartialEq)]
+pub enum Outcome {
+    Success,
+    Failure,
+    Quit,
+}
+
+impl From<bool> for Outcome {
+    fn from(success: bool) -> Self {
+        if success {
+            Outcome::Success
+        } else {
+            Outcome::Failure
+        }
+    }
+}
+
+impl Session {
+    pub fn new(evaluator: Evaluator, reporter: ErrorReporter, debug: bool, json: bool) -> Self {
+        Session {
+            initial: evaluator.clone(),
+            evaluator: Rc::new(RefCell::new(evaluator)),
+            reporter,
+            debug,
+            json,
+            loading: Vec::new(),
+        }
+    }
+
+    /// Runs one line of input, which is either a command or a statement.
+    /// `editor` gives access to the REPL history, if there is one.
+    pub fn execute(
+        &mut self,
+        input: &str,
+        location: Location<'_>,
+        editor: Option<&SevaEditor>,
+    ) -> Outcome {
+        if !commands::is_command(input) {
+            return self.evaluate(input, location).into();
+        }
+
+        match commands::parse_command(input) {
+            Ok(command) => self.run_command(command, input, location, editor),
+            Err(errs) => {
+                if self.json {
+                    CommandReport::syntax_errors(input, location, &errs).print();
+                } else {
+                    self.reporter.report_error(&errs, input, location);
+                }
+                Outcome::Failure
+            }
+        }
+    }
+
+    /// Whether `input` is a well-formed command or statement.
+    pub fn parses(&self, input: &str) -> bool {
+        if commands::is_command(input) {
+            commands::parse_command(input).is_ok()
+        } else {
+            self.evaluator.borrow().parse(input).is_ok()
+        }
+    }
+
+    /// Parses and evaluates one statement, printing the result or the errors.
+    /// Returns whether the evaluation succeeded.
+    pub fn evaluate(&mut self, input: &str, location: Location<'_>) -> bool {
+        let mut evaluator = self.evaluator.borrow_mut();
+        let mut display_mode = None;
+        let result = evaluator.parse(input).and_then(|stmt| {
+            if self.debug && self.json {
+                eprintln!("{}", stmt)
+            } else if self.debug {
+                println!("{}", stmt)
+            };
+            display_mode = stmt.display_mode();
+            evaluator.eval_stmt(stmt)
+        });
+        let fix = evaluator.context().fix();
+        let display_mode = display_mode.unwrap_or(evaluator.context().display_mode());
+        drop(evaluator);
+
+        if self.json {
+            StatementReport::new(input, location, &result, fix, display_mode).print();
+            return result.is_ok();
+        }
+
+        match result {
+            Ok(out) => {
+                out.print(fix, display_mode);
+                true
+            }
+            Err(Error::ParseError(errs)) => {
+                self.reporter.report_error(&errs, input, location);
+                false
+            }
+            Err(Error::EvalError(err)) => {
+                self.reporter.report_eval_error(&err, input, location);
+                false
+            }
+        }
+    }
+
+    /// Runs a command, printing its output and error as text or, with
+    /// `--json`, as one report.
+    fn run_command(
+        &mut self,
+        command: Command,
+        input: &str,
+        location: Location<'_>,
+        editor: Option<&SevaEditor>,
+    ) -> Outcome {
+        let mut output = Vec::new();
+        let result = self.command_output(command, location, editor, &mut output);
+
+        if self.json {
+            let error = result.as_ref().err().map(String::as_str);
+            CommandReport::new(input, location, output, error).print();
+        } else {
+            for line in output {
+                println!("{}", line);
+            }
+            if let Err(message) = &result {
+                self.reporter.report_command_error(message, input, location);
+            }
+        }
+        result.unwrap_or(Outcome::Failure)
+    }
+
+    /// Runs a command, collecting the lines it prints in `output`. Fails with
+    /// the message to report.
+    fn command_output(
+        &mut self,
+        command: Command,
+        location: Location<'_>,
+        editor: Option<&SevaEditor>,
+        output: &mut Vec<String>,
+    ) -> Result<Outcome, String> {
+        match command {
+            Command::Help(None) => {
+                output.extend(commands::usage());
+                let help = self.evaluator.borrow().context().help();
+                output.extend(help.lines().map(String::from));
+            }
+            Command::Help(Some(name)) => {
+                let doc = self
+                    .doc(&name)
+                    .ok_or_else(|| format!("no function or variable named {}", name))?;
+                output.extend(doc);
+            }
+            Command::Quit => return Ok(Outcome::Quit),
+            Command::Vars => {
+                let evaluator = self.evaluator.borrow();
+                let (fix, display_mode) = (
+                    evaluator.context().fix(),
+                    evaluator.context().display_mode(),
+                );
+                let mut variables = evaluator
+                    .variables()
+                    .filter(|(_, variable)| !variable.is_external())
+                    .collect::<Vec<_>>();
+                variables.sort_by_key(|(name, _)| *name);
+                for (name, variable) in variables {
+                    output.push(format!(
+                        "{} = {}",
+                        name,
+                        variable.get().format(fix, display_mode)
+                    ));
+                }
+            }
+            Command::Funcs => {
+                let evaluator = self.evaluator.borrow();
+                let mut functions = evaluator
+                    .functions()
+                    .filter(|(_, function)| !function.is_external())
+                    .collect::<Vec<_>>();
+                functions.sort_by_key(|(name, _)| *name);
+                for (name, function) in functions {
+                    output.extend(definition(name, function));
+                }
+            }
+            Command::Del(name) => {
+                if !self.evaluator.borrow_mut().delete(&name) {
+                    return Err(format!(
+                        "no user-defined variable or function named {}",
+                        name
+                    ));
+                }
+            }
+            Command::Reset => {
+                let mut evaluator = self.evaluator.borrow_mut();
+                let context = evaluator.context();
+                let (angle_unit, fix, display_mode) =
+                    (context.angle_unit(), context.fix(), context.display_mode());
+                *evaluator = self.initial.clone();
+                evaluator.context_mut().set_angle_unit(angle_unit);
+                evaluator.context_mut().set_fix(fix);
+                evaluator.context_mut().set_display_mode(display_mode);
+            }
+            Command::Set(Some(Setting::Fix(fix))) => {
+                self.evaluator.borrow_mut().context_mut().set_fix(fix);
+            }
+            Command::Set(Some(Setting::Mode(angle_unit))) => {
+                self.evaluator
+                    .borrow_mut()
+                    .context_mut()
+                    .set_angle_unit(angle_unit);
+            }
+            Command::Set(Some(Setting::Format(display_mode))) => {
+                self.evaluator
+                    .borrow_mut()
+                    .context_mut()
+                    .set_display_mode(display_mode);
+            }
+            Command::Set(None) => {
+                let evaluator = self.evaluator.borrow();
+                let context = evaluator.context();
+                match context.fix() {
+                    Some(fix) => output.push(format!("fix    {}", fix)),
+                    None => output.push(String::from("fix    none")),
+                }
+                output.push(format!("mode   {}", context.angle_unit()));
+                output.push(format!("format {}", context.display_mode()));
+            }
+            Command::Load(path) => {
+                return self
+                    .run_script_file(&path, false)
+                    .map(Outcome::from)
+                    .map_err(|e| format!("failed to open {}: {}", path, e));
+            }
+            Command::Save(path) => {
+                self.save(Path::new(&path))
+                    .map_err(|e| format!("failed to save {}: {}", path, e))?;
+            }
+            Command::History => {
+                let editor =
+                    editor.ok_or_else(|| String::from("history is only available in the REPL"))?;
+                for (i, line) in editor.history().enumerate() {
+                    output.push(format!("{:>5}  {}", i + 1, line));
+                }
+            }
+            Command::Time(expr) => {
+                let start = Instant::now();
+                let success = self.evaluate(&expr, location);
+                let time = format!("time: {:?}", start.elapsed());
+                if self.json {
+                    output.push(time);
+                } else {
+                    eprintln!("{}", time);
+                }
+                return Ok(success.into());
+            }
+        }
+        Ok(Outcome::Success)
+    }
+
+    /// The documentation of a function, or the value of a variable, if
+    /// `name` is defined.
+    fn doc(&self, name: &str) -> Option<Vec<String>> {
+        let evaluator = self.evaluator.borrow();
+        if let Some(function) = evaluator.function(name) {
+            return Some(match function.doc() {
+                Some(doc) => vec![
+                    doc.signature.to_string(),
+                    format!("    {}", doc.description),
+                    format!("    domain: {}", doc.domain),
+                ],
+                None if function.is_external() => vec![
+                    format!("{}/{}", name, function.arity()),
+                    String::from("    no documentation"),
+                ],
+                None => definition(name, function),
+            });
+        }
+
+        let variable = evaluator.context().get_variable(name)?;
+        let context = evaluator.context();
+        let value = variable.get().format(context.fix(), context.display_mode());
+        let mut doc = vec![format!("{} = {}", name, value)];
+        if variable.is_external() {
+            doc.push(String::from("    constant"));
+        }
+        Some(doc)
+    }
+
+    /// Writes the variables and functions defined since the last snapshot to
+    /// `path` as a script, so that init.evar stays the source of its own
+    /// definitions.
+    pub fn save(&self, path: &Path) -> std::io::Result<()> {
+        let evaluator = self.evaluator.borrow();
+        std::fs::write(path, evaluator.to_script_since(&self.initial))
+    }
+
+    /// Makes the current state the one `:reset` goes back to.
+    pub fn snapshot(&mut self) {
+        self.initial = self.evaluator.borrow().clone();
+    }
+
+    /// Evaluates a script without printing results, such as the rc file or
+    /// a workspace written by `save`. Errors are reported with the file and
+    /// line. Returns whether every line succeeded.
+    pub fn run_silently(&mut self, path: &Path) -> std::io::Result<bool> {
+        let contents = std::fs::read_to_string(path)?;
+        let name = path.display().to_string();
+        let mut success = true;
+
+        for (index, line) in contents.lines().enumerate() {
+            let trimmed = line.trim();
+            if trimmed.is_empty() || trimmed.starts_with('#') {
+                continue;
+            }
+            let location = Location {
+                name: &name,
+                line: index + 1,
+            };
+            let result = self.evaluator.borrow_mut().eval(line);
+            match result {
+                Ok(_) => {}
+                Err(Error::ParseError(errs)) => {
+                    self.reporter.report_error(&errs, line, location);
+                    success = false;
+                }
+                Err(Error::EvalError(err)) => {
+                    self.reporter.report_eval_error(&err, line, location);
+                    success = false;
+                }
+            }
+        }
+
+        Ok(success)
+    }
+
+    /// Evaluates a script line by line, skipping blank lines and `#` comments
+    /// (which includes a `#!` shebang). Stops at the first failing line unless
+    /// `keep_going` is set. Returns whether every line succeeded.
+    pub fn run_script(&mut self, name: &str, reader: impl BufRead, keep_going: bool) -> bool {
+        let mut success = true;
+
+        for (index, line) in reader.lines().enumerate() {
+            let line = match line {
+                Ok(line) => line,
+                Err(e) => {
+                    eprintln!("failed to read {}: {}", name, e);
+                    return false;
+                }
+            };
+
+            let trimmed = line.trim();
+            if trimmed.is_empty() || trimmed.starts_with('#') {
+                continue;
+            }
+
+            let location = Location {
+                name,
+                line: index + 1,
+            };
+
+            match self.execute(&line, location, None) {
+                Outcome::Success => {}
+                Outcome::Failure => {
+                    success = false;
+                    if !keep_going {
+                        break;
+                    }
+                }
+                Outcome::Quit => break,
+            }
+        }
+
+        success
+    }
+
+    /// Runs a script file like [`Session::run_script`]. Fails if the file
+    /// is already being run, as when a script loads itself.
+    pub fn run_script_file(&mut self, path: &str, keep_going: bool) -> std::io::Result<bool> {
+        let file = File::open(path)?;
+        let canonical = std::fs::canonicalize(path)?;
+        if self.loading.contains(&canonical) {
+            return Err(std::io::Error::other("the file is already being loaded"));
+        }
+
+        self.loading.push(canonical);
+        let success = self.run_script(path, BufReader::new(file), keep_going);
+        self.loading.pop();
+        Ok(success)
+    }
+}
+
+/// A user-defined function as it was written, with its docstring.
+fn definition(name: &str, function: &Function) -> Vec<String> {
+    let mut lines = vec![format!(
+        "{}({}) = {}",
+        name,
+        function.arg_names().unwrap_or_default().join(", "),
+        function.source().unwrap_or_default()
+    )];
+    lines.extend(function.docstring().map(|doc| format!("    {}", doc)));
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::theme::{Theme, ThemeColors};
+
+    #[test]
+    fn test_load_itself() {
+        let theme = Theme::new(None, &ThemeColors::default(), false).unwrap();
+        let reporter = ErrorReporter::new(true, &theme);
+        let mut session = Session::new(Evaluator::default(), reporter, false, false);
+
+        let path = std::env::temp_dir().join(format!("evar-load-{}.evar", std::process::id()));
+        let script = format!("let n = 1\n:load {}\n", path.display());
+        std::fs::write(&path, script).unwrap();
+        let path = path.to_str().unwrap();
+
+        assert!(!session.run_script_file(path, false).unwrap());
+        assert_eq!(
+            session.evaluator.borrow().variable("n"),
+            Some(evar::models::Value::from(1))
+        );
+        // the file can be loaded again once the first run has finished
+        assert!(!session.run_script_file(path, true).unwrap());
+        std::fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,273 @@
+use codespan_reporting::term::{
+    Styles,
+    termcolor::{self, ColorSpec},
+};
+use colored::Color::{self, *};
+use serde::{Deserialize, Deserializer, de::Error};
+
+/// Colours of the REPL highlighting and of diagnostics.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Theme {
+    pub number: Color,
+    pub ident: Color,
+    pub operator: Color,
+    pub paren: Color,
+    pub keyword: Color,
+    pub comment: Color,
+    pub error: Color,
+    pub prompt: Color,
+}
+
+/// Colours set in the `[colors]` table of the config file, each replacing
+/// the colour the theme gives that kind of token. Colours are ANSI names
+/// such as `"bright blue"` or hex codes such as `"#1e66f5"`.
+#[derive(Debug, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ThemeColors {
+    #[serde(deserialize_with = "color")]
+    pub number: Option<Color>,
+    #[serde(deserialize_with = "color")]
+    pub ident: Option<Color>,
+    #[serde(deserialize_with = "color")]
+    pub operator: Option<Color>,
+    #[serde(deserialize_with = "color")]
+    pub paren: Option<Color>,
+    #[serde(deserialize_with = "color")]
+    pub keyword: Option<Color>,
+    #[serde(deserialize_with = "color")]
+    pub comment: Option<Color>,
+    #[serde(deserialize_with = "color")]
+    pub error: Option<Color>,
+    #[serde(deserialize_with = "color")]
+    pub prompt: Option<Color>,
+}
+
+/// Names accepted by `theme` in the config file.
+pub const THEMES: [&str; 3] = ["default", "light", "ansi"];
+
+const fn rgb(hex: u32) -> Color {
+    TrueColor {
+        r: (hex >> 16) as u8,
+        g: (hex >> 8) as u8,
+        b: hex as u8,
+    }
+}
+
+/// Catppuccin Macchiato, for dark terminals.
+const DEFAULT: Theme = Theme {
+    number: rgb(0xf5a97f),
+    ident: rgb(0x8aadf4),
+    operator: rgb(0x7dc4e4),
+    paren: rgb(0xeed49f),
+    keyword: rgb(0xc6a0f6),
+    comment: rgb(0x5b6078),
+    error: rgb(0xed8796),
+    prompt: rgb(0xa6da95),
+};
+
+/// Catppuccin Latte, for light terminals.
+const LIGHT: Theme = Theme {
+    number: rgb(0xfe640b),
+    ident: rgb(0x1e66f5),
+    operator: rgb(0x209fb5),
+    paren: rgb(0xdf8e1d),
+    keyword: rgb(0x8839ef),
+    comment: rgb(0x8c8fa1),
+    error: rgb(0xd20f39),
+    prompt: rgb(0x40a02b),
+};
+
+/// The 16 ANSI colours, which the terminal adapts to its background.
+const ANSI: Theme = Theme {
+    number: Yellow,
+    ident: Blue,
+    operator: Cyan,
+    paren: Yellow,
+    keyword: Magenta,
+    comment: BrightBlack,
+    error: Red,
+    prompt: Green,
+};
+
+impl Theme {
+    /// Looks up a named theme and applies the colours from the config on
+    /// top. Without truecolor support, every colour is mapped to the nearest
+    /// of the 16 ANSI colours and the built-in themes use their ANSI variant.
+    pub fn new(name: Option<&str>, colors: &ThemeColors, truecolor: bool) -> Result<Theme, String> {
+        let theme = match name.unwrap_or("default") {
+            "default" => DEFAULT,
+            "light" => LIGHT,
+            "ansi" => ANSI,
+            name => {
+                return Err(format!(
+                    "unknown theme: {} (expected one of {})",
+                    name,
+                    THEMES.join(", ")
+                ));
+            }
+        };
+        let theme = if truecolor { theme } else { ANSI };
+
+        let color = |custom: Option<Color>, color: Color| {
+            let color = custom.unwrap_or(color);
+            if truecolor {
+                color
+            } else {
+                nearest_ansi(color)
+            }
+        };
+
+        Ok(Theme {
+            number: color(colors.number, theme.number),
+            ident: color(colors.ident, theme.ident),
+            operator: color(colors.operator, theme.operator),
+            paren: color(colors.paren, theme.paren),
+            keyword: color(colors.keyword, theme.keyword),
+            comment: color(colors.comment, theme.comment),
+            error: color(colors.error, theme.error),
+            prompt: color(colors.prompt, theme.prompt),
+        })
+    }
+
+    /// Styles of codespan diagnostics in the colours of the theme.
+    pub fn styles(&self) -> Styles {
+        let header = |color| color_spec(color).set_bold(true).clone();
+        Styles {
+            header_bug: header(self.error),
+            header_error: header(self.error),
+            header_warning: header(self.number),
+            header_note: header(self.prompt),
+            header_help: header(self.prompt),
+            header_message: ColorSpec::new().set_bold(true).clone(),
+
+            primary_label_bug: color_spec(self.error),
+            primary_label_error: color_spec(self.error),
+            primary_label_warning: color_spec(self.number),
+            primary_label_note: color_spec(self.prompt),
+            primary_label_help: color_spec(self.prompt),
+            secondary_label: color_spec(self.keyword),
+
+            line_number: color_spec(self.operator),
+            source_border: color_spec(self.operator),
+            note_bullet: color_spec(self.operator),
+        }
+    }
+}
+
+/// Whether the terminal advertises 24-bit colour through `COLORTERM`.
+pub fn truecolor_support() -> bool {
+    std::env::var("COLORTERM").is_ok_and(|value| value == "truecolor" || value == "24bit")
+}
+
+/// The closest of the 16 ANSI colours, in their xterm rendering, to an RGB
+/// colour. Other colours are returned unchanged.
+fn nearest_ansi(color: Color) -> Color {
+    let TrueColor { r, g, b } = color else {
+        return color;
+    };
+    let distance = |(r2, g2, b2): (u8, u8, u8)| {
+        [(r, r2), (g, g2), (b, b2)]
+            .map(|(x, y)| (i32::from(x) - i32::from(y)).pow(2))
+            .iter()
+            .sum::<i32>()
+    };
+    [
+        (Black, (0, 0, 0)),
+        (Red, (205, 0, 0)),
+        (Green, (0, 205, 0)),
+        (Yellow, (205, 205, 0)),
+        (Blue, (0, 0, 238)),
+        (Magenta, (205, 0, 205)),
+        (Cyan, (0, 205, 205)),
+        (White, (229, 229, 229)),
+        (BrightBlack, (127, 127, 127)),
+        (BrightRed, (255, 0, 0)),
+        (BrightGreen, (0, 255, 0)),
+        (BrightYellow, (255, 255, 0)),
+        (BrightBlue, (92, 92, 255)),
+        (BrightMagenta, (255, 0, 255)),
+        (BrightCyan, (0, 255, 255)),
+        (BrightWhite, (255, 255, 255)),
+    ]
+    .into_iter()
+    .min_by_key(|(_, rgb)| distance(*rgb))
+    .map(|(ansi, _)| ansi)
+    .expect("the palette is not empty")
+}
+
+fn color_spec(color: Color) -> ColorSpec {
+    use termcolor::Color as C;
+    let (color, intense) = match color {
+        Black => (C::Black, false),
+        Red => (C::Red, false),
+        Green => (C::Green, false),
+        Yellow => (C::Yellow, false),
+        Blue => (C::Blue, false),
+        Magenta => (C::Magenta, false),
+        Cyan => (C::Cyan, false),
+        White => (C::White, false),
+        BrightBlack => (C::Black, true),
+        BrightRed => (C::Red, true),
+        BrightGreen => (C::Green, true),
+        BrightYellow => (C::Yellow, true),
+        BrightBlue => (C::Blue, true),
+        BrightMagenta => (C::Magenta, true),
+        BrightCyan => (C::Cyan, true),
+        BrightWhite => (C::White, true),
+        AnsiColor(n) => (C::Ansi256(n), false),
+        TrueColor { r, g, b } => (C::Rgb(r, g, b), false),
+    };
+    ColorSpec::new()
+        .set_fg(Some(color))
+        .set_intense(intense)
+        .clone()
+}
+
+fn color<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<Color>, D::Error> {
+    String::deserialize(deserializer)?
+        .parse()
+        .map(Some)
+        .map_err(|()| {
+            D::Error::custom("expected an ANSI colour name or a hex code like \"#1e66f5\"")
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_theme() {
+        let colors = ThemeColors {
+            number: Some(rgb(0xff0000)),
+            keyword: Some(BrightBlue),
+            ..Default::default()
+        };
+
+        let theme = Theme::new(Some("light"), &colors, true).unwrap();
+        assert_eq!(theme.ident, LIGHT.ident);
+        assert_eq!(theme.number, rgb(0xff0000));
+        assert_eq!(theme.keyword, BrightBlue);
+
+        let theme = Theme::new(Some("light"), &colors, false).unwrap();
+        assert_eq!(theme.ident, ANSI.ident);
+        assert_eq!(theme.number, BrightRed);
+        assert_eq!(theme.keyword, BrightBlue);
+
+        assert!(Theme::new(Some("neon"), &colors, true).is_err());
+    }
+
+    #[test]
+    fn test_parse_colors() {
+        let colors: ThemeColors = toml::from_str(
+            r##"
+            number = "#1e66f5"
+            comment = "bright black"
+            "##,
+        )
+        .unwrap();
+        assert_eq!(colors.number, Some(rgb(0x1e66f5)));
+        assert_eq!(colors.comment, Some(BrightBlack));
+        assert!(toml::from_str::<ThemeColors>("number = \"ultraviolet\"").is_err());
+    }
+}
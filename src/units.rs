@@ -0,0 +1,118 @@
+use crate::models::{BaseUnit, Dimension};
+use std::collections::HashMap;
+use std::f64::consts::PI;
+use std::rc::Rc;
+
+const PREFIXES: [(&str, f64); 21] = [
+    ("Q", 1e30),
+    ("R", 1e27),
+    ("Y", 1e24),
+    ("Z", 1e21),
+    ("E", 1e18),
+    ("P", 1e15),
+    ("T", 1e12),
+    ("G", 1e9),
+    ("M", 1e6),
+    ("k", 1e3),
+    ("h", 1e2),
+    ("da", 1e1),
+    ("d", 1e-1),
+    ("c", 1e-2),
+    ("m", 1e-3),
+    ("u", 1e-6),
+    ("n", 1e-9),
+    ("p", 1e-12),
+    ("f", 1e-15),
+    ("a", 1e-18),
+    ("z", 1e-21),
+];
+
+#[rustfmt::skip]
+pub fn create_units() -> HashMap<String, Rc<BaseUnit>> {
+    use Dimension as D;
+
+    let length = D::LENGTH;
+    let mass = D::MASS;
+    let time = D::TIME;
+    let current = D::CURRENT;
+    let force = mass * length / time.powi(2);
+    let energy = force * length;
+    let power = energy / time;
+    let pressure = force / length.powi(2);
+    let volume = length.powi(3);
+    let voltage = power / current;
+
+    // (name, dimension, scale in SI units, accepts SI prefixes)
+    let definitions = [
+        // SI base units
+        ("m", length, 1., true),
+        ("g", mass, 1e-3, true),
+        ("s", time, 1., true),
+        ("A", current, 1., true),
+        ("K", D::TEMPERATURE, 1., true),
+        ("mol", D::AMOUNT, 1., true),
+        ("cd", D::LUMINOSITY, 1., true),
+        ("rad", D::ANGLE, 1., true),
+        // SI derived units
+        ("Hz", time.powi(-1), 1., true),
+        ("N", force, 1., true),
+        ("Pa", pressure, 1., true),
+        ("J", energy, 1., true),
+        ("W", power, 1., true),
+        ("C", current * time, 1., true),
+        ("V", voltage, 1., true),
+        ("ohm", voltage / current, 1., true),
+        ("L", volume, 1e-3, true),
+        ("eV", energy, 1.602176634e-19, true),
+        ("cal", energy, 4.184, true),
+        ("bar", pressure, 1e5, true),
+        // time
+        ("min", time, 60., false),
+        ("h", time, 3600., false),
+        ("day", time, 86400., false),
+        ("week", time, 604800., false),
+        ("yr", time, 31557600., false),
+        // angle
+        ("deg", D::ANGLE, PI / 180., false),
+        ("arcmin", D::ANGLE, PI / 10800., false),
+        ("arcsec", D::ANGLE, PI / 648000., false),
+        ("grad", D::ANGLE, PI / 200., false),
+        // imperial and US customary units
+        ("inch", length, 0.0254, false),
+        ("ft", length, 0.3048, false),
+        ("yd", length, 0.9144, false),
+        ("mi", length, 1609.344, false),
+        ("nmi", length, 1852., false),
+        ("acre", length.powi(2), 4046.8564224, false),
+        ("floz", volume, 2.95735295625e-5, false),
+        ("pt", volume, 4.73176473e-4, false),
+        ("qt", volume, 9.46352946e-4, false),
+        ("gal", volume, 3.785411784e-3, false),
+        ("oz", mass, 0.028349523125, false),
+        ("lb", mass, 0.45359237, false),
+        ("st", mass, 6.35029318, false),
+        ("t", mass, 1000., false),
+        ("mph", length / time, 0.44704, false),
+        ("kn", length / time, 1852. / 3600., false),
+        ("lbf", force, 4.4482216152605, false),
+        ("psi", pressure, 6894.757293168, false),
+        ("atm", pressure, 101325., false),
+        ("Btu", energy, 1055.05585262, false),
+        ("hp", power, 745.69987158227, false),
+    ];
+
+    let mut units = HashMap::new();
+    for (name, dimension, scale, prefixable) in definitions {
+        if prefixable {
+            for (prefix, factor) in PREFIXES {
+                let name = format!("{}{}", prefix, name);
+                units.entry(name.clone()).or_insert_with(|| {
+                    Rc::new(BaseUnit { name, dimension, scale: scale * factor })
+                });
+            }
+        }
+        let name = name.to_string();
+        units.insert(name.clone(), Rc::new(BaseUnit { name, dimension, scale }));
+    }
+    units
+}